@@ -4,7 +4,7 @@ use std::{fmt::Debug, rc::Rc};
 /// File definition, it contains all the statements,
 /// the module name, and a base location for it as anchor
 /// for the statements.
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct File {
     pub name: String,
     pub expression: Term,
@@ -23,7 +23,7 @@ impl<T: Element> Element for Box<T> {
     }
 }
 
-#[derive(Default, Hash, PartialEq, Eq, Clone, serde::Deserialize)]
+#[derive(Default, Hash, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Location {
     pub start: usize,
     pub end: usize,
@@ -43,7 +43,7 @@ impl Location {
 
 impl Debug for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Location")
+        write!(f, "{}@{}..{}", self.filename, self.start, self.end)
     }
 }
 
@@ -52,10 +52,33 @@ pub trait Element {
     fn location(&self) -> &Location;
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+/// Where a variable lives at runtime: `depth` frames above the current one,
+/// at index `slot`. Filled in by [`crate::resolver`].
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct Address {
+    pub depth: usize,
+    pub slot: usize,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Var {
     pub text: String,
     pub location: Location,
+
+    /// The resolved address, `None` for globals or before resolution.
+    #[serde(skip)]
+    pub address: Option<Address>,
+}
+
+impl Var {
+    /// Creates a new, unresolved instance of [`Var`].
+    pub fn new(text: &str, location: Location) -> Self {
+        Self {
+            text: text.into(),
+            location,
+            address: None,
+        }
+    }
 }
 
 impl Element for Var {
@@ -64,7 +87,7 @@ impl Element for Var {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct If {
     pub condition: Box<Term>,
     pub then: Box<Term>,
@@ -72,7 +95,7 @@ pub struct If {
     pub location: Location,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Let {
     pub name: Var,
     pub value: Box<Term>,
@@ -81,7 +104,7 @@ pub struct Let {
 }
 
 /// Int is a integer value like `0`, `1`, `2`, etc.
-#[derive(Default, Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Str {
     pub value: String,
 
@@ -95,7 +118,7 @@ impl Element for Str {
     }
 }
 
-#[derive(Default, Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Bool {
     pub value: bool,
     pub location: Location,
@@ -108,7 +131,7 @@ impl Element for Bool {
 }
 
 /// Int is a integer value like `0`, `1`, `2`, etc.
-#[derive(Default, Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Int {
     /// The value of the integer.
     pub value: i64,
@@ -123,7 +146,7 @@ impl Element for Int {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub enum BinaryOp {
     Add, // Add
     Sub, // Subtract
@@ -140,7 +163,41 @@ pub enum BinaryOp {
     Or,  // Or
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+impl BinaryOp {
+    /// Binding power of the operator, higher binds tighter. Operators of the
+    /// same precedence associate to the left.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Neq => 3,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Lte | BinaryOp::Gte => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 6,
+        }
+    }
+
+    /// How the operator is written in rinha source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Neq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Lte => "<=",
+            BinaryOp::Gte => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Binary {
     pub lhs: Box<Term>,
     pub op: BinaryOp,
@@ -154,7 +211,7 @@ impl Element for Binary {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Call {
     pub callee: Box<Term>,
     pub arguments: Vec<Term>,
@@ -167,11 +224,26 @@ impl Element for Call {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Function {
     pub parameters: Vec<Var>,
     pub value: Box<Term>,
     pub location: Location,
+
+    /// Variables the body uses from outside the function, addressed from
+    /// the frame it is defined in. Filled in by [`crate::resolver`].
+    #[serde(skip)]
+    pub free: Vec<Var>,
+
+    /// Whether calling the function can never print, making it safe to
+    /// memoize. Filled in by [`crate::effects`].
+    #[serde(skip)]
+    pub pure: bool,
+
+    /// Identifies the function literal in memoization keys, unique across
+    /// every analyzed program. Filled in by [`crate::effects`].
+    #[serde(skip)]
+    pub id: usize,
 }
 
 impl Element for Function {
@@ -180,7 +252,7 @@ impl Element for Function {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Print {
     pub value: Box<Term>,
     pub location: Location,
@@ -192,7 +264,7 @@ impl Element for Print {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct First {
     pub value: Box<Term>,
     pub location: Location,
@@ -204,7 +276,7 @@ impl Element for First {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Second {
     pub value: Box<Term>,
     pub location: Location,
@@ -216,7 +288,7 @@ impl Element for Second {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 pub struct Tuple {
     pub first: Box<Term>,
     pub second: Box<Term>,
@@ -229,14 +301,15 @@ impl Element for Tuple {
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Hash, PartialEq, Eq)]
 #[serde(tag = "kind")]
 pub enum Term {
     Int(Int),
     Str(Str),
     Call(Call),
     Binary(Binary),
-    Function(Function),
+    /// Shared, so closures of the function don't copy its body.
+    Function(Rc<Function>),
     Let(Let),
     If(If),
     Print(Print),
@@ -267,12 +340,49 @@ impl Element for Term {
     }
 }
 
-impl Term {
-    pub fn is_pure(&self) -> bool {
-        match self {
-            Term::Function(function) => function.value.is_pure(),
-            Term::Print(_) => false,
-            _term => true,
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::File;
+
+    #[test]
+    fn json_round_trip() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut checked = 0;
+
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+
+            let text = std::fs::read_to_string(&path).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let file: File = serde_json::from_str(&text).unwrap();
+
+            assert_eq!(serde_json::to_value(&file).unwrap(), json, "{path:?}");
+
+            let reparsed: File =
+                serde_json::from_str(&serde_json::to_string(&file).unwrap()).unwrap();
+            assert_eq!(reparsed.expression, file.expression, "{path:?}");
+            checked += 1;
         }
+
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn serializes_parsed_source() {
+        let file = crate::parser::parse("print((1, true))", "tests").unwrap();
+        let json = serde_json::to_value(&file).unwrap();
+
+        assert_eq!(json["expression"]["kind"], "Print");
+        assert_eq!(json["expression"]["value"]["kind"], "Tuple");
+        assert_eq!(json["expression"]["value"]["second"]["value"], true);
+        assert_eq!(
+            json["expression"]["location"],
+            serde_json::json!({ "start": 0, "end": 16, "filename": "tests" })
+        );
     }
 }
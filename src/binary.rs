@@ -1,13 +1,144 @@
+use std::{cmp::Ordering, rc::Rc};
+
+use num_bigint::BigInt;
+
 use crate::{
     ast::{Binary, BinaryOp, Element, Location},
     interpreter::{RuntimeError, Value},
 };
 
+/// How integer arithmetic handles results that don't fit in an `i64`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail with a runtime error.
+    #[default]
+    Error,
+    /// Continue with arbitrary precision integers.
+    Promote,
+}
+
+impl From<BigInt> for Value {
+    /// Integers that fit in an `i64` are always [`Value::Int`], so every
+    /// integer has a single representation.
+    fn from(int: BigInt) -> Self {
+        match i64::try_from(&int) {
+            Ok(int) => Value::Int(int),
+            Err(_) => Value::BigInt(Rc::new(int)),
+        }
+    }
+}
+
 fn invalid_comparison(l_value: &Value, r_value: &Value, location: &Location) -> RuntimeError {
     RuntimeError {
         message: String::from("invalid comparison"),
         full_text: format!("{} and {} cannot be compared", l_value, r_value),
         location: location.clone(),
+        note: None,
+    }
+}
+
+fn invalid_and(location: &Location) -> RuntimeError {
+    RuntimeError {
+        message: String::from("invalid AND operation"),
+        full_text: String::from("only booleans can be used on short-circuit operations"),
+        location: location.clone(),
+        note: None,
+    }
+}
+
+fn invalid_or(location: &Location) -> RuntimeError {
+    RuntimeError {
+        message: String::from("invalid OR operation"),
+        full_text: String::from("only booleans can be used on short-circuit operations"),
+        location: location.clone(),
+        note: None,
+    }
+}
+
+fn big(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Int(int) => Some(BigInt::from(*int)),
+        Value::BigInt(int) => Some(int.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// Compares integers of either representation, `None` if one of them isn't
+/// an integer.
+fn compare_integers(l_value: &Value, r_value: &Value) -> Option<Ordering> {
+    match (l_value, r_value) {
+        (Value::Int(l_int), Value::Int(r_int)) => Some(l_int.cmp(r_int)),
+        _ => Some(big(l_value)?.cmp(&big(r_value)?)),
+    }
+}
+
+/// An integer operation, checked when both operands are `i64`s.
+struct Arithmetic {
+    symbol: &'static str,
+    checked: fn(i64, i64) -> Option<i64>,
+    promoted: fn(BigInt, BigInt) -> BigInt,
+}
+
+const ADD: Arithmetic = Arithmetic {
+    symbol: "+",
+    checked: i64::checked_add,
+    promoted: |l, r| l + r,
+};
+
+const SUB: Arithmetic = Arithmetic {
+    symbol: "-",
+    checked: i64::checked_sub,
+    promoted: |l, r| l - r,
+};
+
+const MUL: Arithmetic = Arithmetic {
+    symbol: "*",
+    checked: i64::checked_mul,
+    promoted: |l, r| l * r,
+};
+
+const DIV: Arithmetic = Arithmetic {
+    symbol: "/",
+    checked: i64::checked_div,
+    promoted: |l, r| l / r,
+};
+
+const REM: Arithmetic = Arithmetic {
+    symbol: "%",
+    checked: i64::checked_rem,
+    promoted: |l, r| l % r,
+};
+
+impl Arithmetic {
+    /// Applies the operation to integers of either representation, `None`
+    /// if one of them isn't an integer.
+    fn apply(
+        &self,
+        l_value: &Value,
+        r_value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Option<Result<Value, RuntimeError>> {
+        if let (Value::Int(l_int), Value::Int(r_int)) = (l_value, r_value) {
+            match ((self.checked)(*l_int, *r_int), overflow) {
+                (Some(int), _) => return Some(Ok(Value::Int(int))),
+                (None, Overflow::Error) => {
+                    return Some(Err(RuntimeError {
+                        message: String::from("integer overflow"),
+                        full_text: format!(
+                            "{l_int} {} {r_int} does not fit in a 64-bit integer",
+                            self.symbol
+                        ),
+                        location: location.clone(),
+                        note: None,
+                    }))
+                }
+                (None, Overflow::Promote) => {}
+            }
+        }
+
+        let int = (self.promoted)(big(l_value)?, big(r_value)?);
+        Some(Ok(Value::from(int)))
     }
 }
 
@@ -16,8 +147,10 @@ impl Value {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(l_bool == r_bool)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Bool(l_str == r_str)),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int == r_int)),
-            (l_value, r_value) => Err(invalid_comparison(l_value, r_value, location)),
+            (l_value, r_value) => match compare_integers(l_value, r_value) {
+                Some(ordering) => Ok(Value::Bool(ordering.is_eq())),
+                None => Err(invalid_comparison(l_value, r_value, location)),
+            },
         }
     }
 
@@ -25,8 +158,10 @@ impl Value {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(l_bool != r_bool)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Bool(l_str != r_str)),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int != r_int)),
-            (l_value, r_value) => Err(invalid_comparison(l_value, r_value, location)),
+            (l_value, r_value) => match compare_integers(l_value, r_value) {
+                Some(ordering) => Ok(Value::Bool(ordering.is_ne())),
+                None => Err(invalid_comparison(l_value, r_value, location)),
+            },
         }
     }
 
@@ -34,8 +169,10 @@ impl Value {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(l_bool < r_bool)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Bool(l_str < r_str)),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int < r_int)),
-            (l_value, r_value) => Err(invalid_comparison(l_value, r_value, location)),
+            (l_value, r_value) => match compare_integers(l_value, r_value) {
+                Some(ordering) => Ok(Value::Bool(ordering.is_lt())),
+                None => Err(invalid_comparison(l_value, r_value, location)),
+            },
         }
     }
 
@@ -43,8 +180,10 @@ impl Value {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(l_bool <= r_bool)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Bool(l_str <= r_str)),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int <= r_int)),
-            (l_value, r_value) => Err(invalid_comparison(l_value, r_value, location)),
+            (l_value, r_value) => match compare_integers(l_value, r_value) {
+                Some(ordering) => Ok(Value::Bool(ordering.is_le())),
+                None => Err(invalid_comparison(l_value, r_value, location)),
+            },
         }
     }
 
@@ -52,8 +191,10 @@ impl Value {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(l_bool > r_bool)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Bool(l_str > r_str)),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int > r_int)),
-            (l_value, r_value) => Err(invalid_comparison(l_value, r_value, location)),
+            (l_value, r_value) => match compare_integers(l_value, r_value) {
+                Some(ordering) => Ok(Value::Bool(ordering.is_gt())),
+                None => Err(invalid_comparison(l_value, r_value, location)),
+            },
         }
     }
 
@@ -61,123 +202,201 @@ impl Value {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(l_bool >= r_bool)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Bool(l_str >= r_str)),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Bool(l_int >= r_int)),
-            (l_value, r_value) => Err(invalid_comparison(l_value, r_value, location)),
+            (l_value, r_value) => match compare_integers(l_value, r_value) {
+                Some(ordering) => Ok(Value::Bool(ordering.is_ge())),
+                None => Err(invalid_comparison(l_value, r_value, location)),
+            },
         }
     }
 
     pub fn and(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(*l_bool && *r_bool)),
-            (_l_val, _r_val) => Err(RuntimeError {
-                message: String::from("invalid AND operation"),
-                full_text: format!("only booleans can be used on short-circuit operations"),
-                location: location.clone(),
-            }),
+            (_l_val, _r_val) => Err(invalid_and(location)),
         }
     }
 
     pub fn or(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
         match (self, value) {
             (Value::Bool(l_bool), Value::Bool(r_bool)) => Ok(Value::Bool(*l_bool || *r_bool)),
-            (_l_val, _r_val) => Err(RuntimeError {
-                message: String::from("invalid OR operation"),
-                full_text: format!("only booleans can be used on short-circuit operations"),
-                location: location.clone(),
-            }),
+            (_l_val, _r_val) => Err(invalid_or(location)),
         }
     }
 
-    pub fn add(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
+    pub fn add(
+        &self,
+        value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(result) = ADD.apply(self, value, location, overflow) {
+            return result;
+        }
+
         match (self, value) {
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int + r_int)),
             (Value::Str(l_str), Value::Str(r_str)) => Ok(Value::Str(format!("{l_str}{r_str}"))),
-            (Value::Str(l_str), Value::Int(r_int)) => Ok(Value::Str(format!("{l_str}{r_int}"))),
-            (Value::Int(l_int), Value::Str(r_str)) => Ok(Value::Str(format!("{l_int}{r_str}"))),
+            (Value::Str(l_str), r_int @ (Value::Int(_) | Value::BigInt(_))) => {
+                Ok(Value::Str(format!("{l_str}{r_int}")))
+            }
+            (l_int @ (Value::Int(_) | Value::BigInt(_)), Value::Str(r_str)) => {
+                Ok(Value::Str(format!("{l_int}{r_str}")))
+            }
             (l_val, r_val) => Err(RuntimeError {
                 message: String::from("invalid addition"),
                 full_text: format!("{l_val} cannot be added to {r_val}",),
                 location: location.clone(),
+                note: None,
             }),
         }
     }
 
-    pub fn sub(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
-        match (self, value) {
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int - r_int)),
-            (l_val, r_val) => Err(RuntimeError {
-                message: String::from("invalid subtraction"),
-                full_text: format!("{l_val} cannot be subtracted by {r_val}",),
-                location: location.clone(),
-            }),
-        }
-    }
-
-    pub fn mul(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
-        match (self, value) {
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int * r_int)),
-            (l_val, r_val) => Err(RuntimeError {
-                message: String::from("invalid multiplication"),
-                full_text: format!("{l_val} cannot be multiplied by {r_val} ",),
-                location: location.clone(),
-            }),
-        }
-    }
-
-    pub fn div(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
-        match (self, value) {
-            (Value::Int(_l_int), Value::Int(0)) => Err(RuntimeError {
+    pub fn sub(
+        &self,
+        value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        SUB.apply(self, value, location, overflow)
+            .unwrap_or_else(|| {
+                Err(RuntimeError {
+                    message: String::from("invalid subtraction"),
+                    full_text: format!("{self} cannot be subtracted by {value}",),
+                    location: location.clone(),
+                    note: None,
+                })
+            })
+    }
+
+    pub fn mul(
+        &self,
+        value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        MUL.apply(self, value, location, overflow)
+            .unwrap_or_else(|| {
+                Err(RuntimeError {
+                    message: String::from("invalid multiplication"),
+                    full_text: format!("{self} cannot be multiplied by {value} ",),
+                    location: location.clone(),
+                    note: None,
+                })
+            })
+    }
+
+    pub fn div(
+        &self,
+        value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        if let (Value::Int(_) | Value::BigInt(_), Value::Int(0)) = (self, value) {
+            return Err(RuntimeError {
                 message: String::from("division by zero"),
                 full_text: String::from("zero cannot be divised"),
                 location: location.clone(),
-            }),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int / r_int)),
-            (l_val, r_val) => Err(RuntimeError {
-                message: String::from("invalid division"),
-                full_text: format!("{l_val} cannot be divised by {r_val}",),
-                location: location.clone(),
-            }),
+                note: None,
+            });
         }
-    }
 
-    pub fn rem(&self, value: &Value, location: &Location) -> Result<Value, RuntimeError> {
-        match (self, value) {
-            (Value::Int(_l_val), Value::Int(0)) => Err(RuntimeError {
+        DIV.apply(self, value, location, overflow)
+            .unwrap_or_else(|| {
+                Err(RuntimeError {
+                    message: String::from("invalid division"),
+                    full_text: format!("{self} cannot be divised by {value}",),
+                    location: location.clone(),
+                    note: None,
+                })
+            })
+    }
+
+    pub fn rem(
+        &self,
+        value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        if let (Value::Int(_) | Value::BigInt(_), Value::Int(0)) = (self, value) {
+            return Err(RuntimeError {
                 message: String::from("division by zero"),
                 full_text: String::from("cannot get remainder from a zero division"),
                 location: location.clone(),
-            }),
-            (Value::Int(l_int), Value::Int(r_int)) => Ok(Value::Int(l_int % r_int)),
-            (l_val, r_val) => Err(RuntimeError {
-                message: String::from("invalid remainder operation"),
-                full_text: format!("cannot get remainder from {l_val} and {r_val} division"),
-                location: location.clone(),
-            }),
+                note: None,
+            });
+        }
+
+        REM.apply(self, value, location, overflow)
+            .unwrap_or_else(|| {
+                Err(RuntimeError {
+                    message: String::from("invalid remainder operation"),
+                    full_text: format!("cannot get remainder from {self} and {value} division"),
+                    location: location.clone(),
+                    note: None,
+                })
+            })
+    }
+
+    /// Applies `op` to both operands, reporting errors at `location`.
+    pub fn apply(
+        &self,
+        op: &BinaryOp,
+        value: &Value,
+        location: &Location,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        match op {
+            BinaryOp::Eq => self.eq(value, location),
+            BinaryOp::Neq => self.neq(value, location),
+            BinaryOp::Lt => self.lt(value, location),
+            BinaryOp::Lte => self.lte(value, location),
+            BinaryOp::Gt => self.gt(value, location),
+            BinaryOp::Gte => self.gte(value, location),
+            BinaryOp::And => self.and(value, location),
+            BinaryOp::Or => self.or(value, location),
+            BinaryOp::Add => self.add(value, location, overflow),
+            BinaryOp::Sub => self.sub(value, location, overflow),
+            BinaryOp::Mul => self.mul(value, location, overflow),
+            BinaryOp::Div => self.div(value, location, overflow),
+            BinaryOp::Rem => self.rem(value, location, overflow),
         }
     }
 
-    pub fn binary_op(self, binary: Binary, rhs: Value) -> Result<Value, RuntimeError> {
-        match binary.op {
-            BinaryOp::Eq => self.eq(&rhs, binary.lhs.location()),
-            BinaryOp::Neq => self.neq(&rhs, binary.lhs.location()),
-            BinaryOp::Lt => self.lt(&rhs, binary.lhs.location()),
-            BinaryOp::Lte => self.lte(&rhs, binary.lhs.location()),
-            BinaryOp::Gt => self.gt(&rhs, binary.lhs.location()),
-            BinaryOp::Gte => self.gte(&rhs, binary.lhs.location()),
-            BinaryOp::And => self.and(&rhs, binary.lhs.location()),
-            BinaryOp::Or => self.or(&rhs, binary.lhs.location()),
-            BinaryOp::Add => self.add(&rhs, binary.lhs.location()),
-            BinaryOp::Sub => self.sub(&rhs, binary.lhs.location()),
-            BinaryOp::Mul => self.mul(&rhs, binary.lhs.location()),
-            BinaryOp::Div => self.div(&rhs, binary.lhs.location()),
-            BinaryOp::Rem => self.rem(&rhs, binary.lhs.location()),
+    /// The result of `op` when this left hand side alone decides it, like
+    /// `false && x`, or `None` when the right hand side has to be evaluated.
+    pub fn short_circuit(
+        &self,
+        op: &BinaryOp,
+        location: &Location,
+    ) -> Result<Option<Value>, RuntimeError> {
+        match (op, self) {
+            (BinaryOp::And, Value::Bool(false)) | (BinaryOp::Or, Value::Bool(true)) => {
+                Ok(Some(self.clone()))
+            }
+            (BinaryOp::And | BinaryOp::Or, Value::Bool(_)) => Ok(None),
+            (BinaryOp::And, _) => Err(invalid_and(location)),
+            (BinaryOp::Or, _) => Err(invalid_or(location)),
+            _ => Ok(None),
         }
     }
+
+    pub fn binary_op(
+        &self,
+        binary: &Binary,
+        rhs: &Value,
+        overflow: Overflow,
+    ) -> Result<Value, RuntimeError> {
+        self.apply(&binary.op, rhs, binary.lhs.location(), overflow)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{ast::Location, interpreter::Value};
+    use crate::{
+        ast::{BinaryOp, Location},
+        interpreter::Value,
+    };
+
+    use super::Overflow;
 
     fn int(int: i64) -> Value {
         Value::Int(int)
@@ -204,62 +423,64 @@ mod tests {
 
     #[test]
     fn add_int_int() {
-        let three_add_five = int(3).add(&int(5), &location()).unwrap();
+        let three_add_five = int(3).add(&int(5), &location(), Overflow::Error).unwrap();
         assert!(eq(&three_add_five, &int(8)));
     }
 
     #[test]
     fn add_str_int() {
-        let a_add_two = str("a").add(&int(2), &location()).unwrap();
+        let a_add_two = str("a").add(&int(2), &location(), Overflow::Error).unwrap();
         assert!(eq(&a_add_two, &str("a2")));
     }
 
     #[test]
     fn add_int_str() {
-        let two_add_a = int(2).add(&str("a"), &location()).unwrap();
+        let two_add_a = int(2).add(&str("a"), &location(), Overflow::Error).unwrap();
         assert!(eq(&two_add_a, &str("2a")));
     }
 
     #[test]
     fn add_str_str() {
-        let a_add_a = str("a").add(&str("b"), &location()).unwrap();
+        let a_add_a = str("a")
+            .add(&str("b"), &location(), Overflow::Error)
+            .unwrap();
         assert!(eq(&a_add_a, &str("ab")));
     }
 
     #[test]
     fn sub() {
-        let zero_sub_one = int(0).sub(&int(1), &location()).unwrap();
+        let zero_sub_one = int(0).sub(&int(1), &location(), Overflow::Error).unwrap();
         assert!(eq(&zero_sub_one, &int(-1)));
     }
 
     #[test]
     fn mul() {
-        let two_mul_two = int(2).mul(&int(2), &location()).unwrap();
+        let two_mul_two = int(2).mul(&int(2), &location(), Overflow::Error).unwrap();
         assert!(eq(&two_mul_two, &int(4)));
     }
 
     #[test]
     fn div() {
-        let three_div_two = int(3).div(&int(2), &location()).unwrap();
+        let three_div_two = int(3).div(&int(2), &location(), Overflow::Error).unwrap();
         assert!(eq(&three_div_two, &int(1)));
     }
 
     #[test]
     fn div_by_zero() {
-        let is_err = int(1).div(&int(0), &location()).is_err();
+        let is_err = int(1).div(&int(0), &location(), Overflow::Error).is_err();
 
         assert!(is_err);
     }
 
     #[test]
     fn rem() {
-        let four_rem_two = int(4).rem(&int(2), &location()).unwrap();
+        let four_rem_two = int(4).rem(&int(2), &location(), Overflow::Error).unwrap();
         assert!(eq(&four_rem_two, &int(0)));
     }
 
     #[test]
     fn rem_with_zero() {
-        let is_err = int(1).rem(&int(0), &location()).is_err();
+        let is_err = int(1).rem(&int(0), &location(), Overflow::Error).is_err();
 
         assert!(is_err);
     }
@@ -273,7 +494,10 @@ mod tests {
     #[test]
     fn eq_int() {
         let two_eq_one_plus_one = int(2)
-            .eq(&int(1).add(&int(1), &location()).unwrap(), &location())
+            .eq(
+                &int(1).add(&int(1), &location(), Overflow::Error).unwrap(),
+                &location(),
+            )
             .unwrap();
         assert!(eq(&two_eq_one_plus_one, &Value::Bool(true)));
     }
@@ -295,7 +519,10 @@ mod tests {
     #[test]
     fn neq_int() {
         let three_neq_one_plus_one = int(3)
-            .neq(&int(1).add(&int(1), &location()).unwrap(), &location())
+            .neq(
+                &int(1).add(&int(1), &location(), Overflow::Error).unwrap(),
+                &location(),
+            )
             .unwrap();
         assert!(eq(&three_neq_one_plus_one, &Value::Bool(true)));
     }
@@ -340,6 +567,21 @@ mod tests {
         assert!(eq(&true_and_false, &Value::Bool(false)));
     }
 
+    #[test]
+    fn short_circuit() {
+        let and = Value::Bool(false)
+            .short_circuit(&BinaryOp::And, &location())
+            .unwrap();
+        assert!(eq(&and.unwrap(), &Value::Bool(false)));
+
+        let or = Value::Bool(false)
+            .short_circuit(&BinaryOp::Or, &location())
+            .unwrap();
+        assert!(or.is_none());
+
+        assert!(int(1).short_circuit(&BinaryOp::Or, &location()).is_err());
+    }
+
     #[test]
     fn or_bool() {
         let false_or_true = Value::Bool(false)
@@ -347,4 +589,53 @@ mod tests {
             .unwrap();
         assert!(eq(&false_or_true, &Value::Bool(true)));
     }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let error = int(i64::MAX)
+            .add(&int(1), &location(), Overflow::Error)
+            .unwrap_err();
+        assert_eq!(error.message, "integer overflow");
+
+        assert!(int(i64::MIN)
+            .div(&int(-1), &location(), Overflow::Error)
+            .is_err());
+    }
+
+    #[test]
+    fn overflow_promotes_to_big_integers() {
+        let max_plus_one = int(i64::MAX)
+            .add(&int(1), &location(), Overflow::Promote)
+            .unwrap();
+        assert!(matches!(max_plus_one, Value::BigInt(_)));
+        assert_eq!(max_plus_one.to_string(), "9223372036854775808");
+
+        let squared = max_plus_one
+            .mul(&max_plus_one, &location(), Overflow::Promote)
+            .unwrap();
+        assert_eq!(
+            squared.to_string(),
+            "85070591730234615865843651857942052864"
+        );
+        assert!(eq(
+            &max_plus_one.lt(&squared, &location()).unwrap(),
+            &Value::Bool(true)
+        ));
+        assert!(eq(
+            &int(1).gt(&max_plus_one, &location()).unwrap(),
+            &Value::Bool(false)
+        ));
+    }
+
+    #[test]
+    fn big_integers_fitting_in_64_bits_are_demoted() {
+        let max_plus_one = int(i64::MAX)
+            .add(&int(1), &location(), Overflow::Promote)
+            .unwrap();
+        let max = max_plus_one
+            .sub(&int(1), &location(), Overflow::Promote)
+            .unwrap();
+
+        assert!(matches!(max, Value::Int(i64::MAX)));
+    }
 }
@@ -0,0 +1,365 @@
+use std::{cell::RefCell, io, num::IntErrorKind, rc::Rc};
+
+use num_bigint::BigInt;
+
+use crate::{
+    input::Input,
+    interpreter::{Context, Value},
+    native::{Native, NativeError},
+    typechecker::Type,
+};
+
+/// Error for a substring out of the bounds of its string.
+fn out_of_bounds(start: i64, end: i64, length: usize) -> NativeError {
+    NativeError::new(
+        "index out of bounds",
+        &format!("substring {start}..{end} of a string of length {length}"),
+    )
+}
+
+fn substring(str: String, start: i64, end: i64) -> Result<String, NativeError> {
+    let length = str.chars().count();
+    let bounds = usize::try_from(start).and_then(|start| Ok((start, usize::try_from(end)?)));
+
+    match bounds {
+        Ok((from, to)) if from <= to && to <= length => {
+            Ok(str.chars().skip(from).take(to - from).collect())
+        }
+        _ => Err(out_of_bounds(start, end, length)),
+    }
+}
+
+fn parse_int(str: String) -> Result<i64, NativeError> {
+    str.parse()
+        .map_err(|err: std::num::ParseIntError| match err.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => NativeError::new(
+                "integer overflow",
+                &format!("{str} does not fit in a 64-bit integer"),
+            ),
+            _ => NativeError::new("invalid integer", &format!("\"{str}\" is not an integer")),
+        })
+}
+
+fn abs(int: i64) -> Result<i64, NativeError> {
+    int.checked_abs().ok_or_else(|| {
+        NativeError::new(
+            "integer overflow",
+            &format!("abs({int}) does not fit in a 64-bit integer"),
+        )
+    })
+}
+
+/// The builtins reading from `input`: `read_line`, returning the next line
+/// without its line ending or an empty string once the input is over, and
+/// `eof`, telling an empty line from the end of the input. Values printed
+/// before either call are shown first.
+pub fn input<I: Input + 'static>(input: I) -> Vec<Native> {
+    let input = Rc::new(RefCell::new(input));
+    let failed = |name: &str, err: io::Error| {
+        NativeError::new("failed to read input", &format!("{name} failed: {err}"))
+    };
+
+    let lines = input.clone();
+    let read_line = Native::new("read_line", move || {
+        let line = lines
+            .borrow_mut()
+            .read_line()
+            .map_err(|err| failed("read_line", err))?;
+
+        Ok::<_, NativeError>(line.unwrap_or_default())
+    });
+    let eof = Native::new("eof", move || {
+        input
+            .borrow_mut()
+            .at_end()
+            .map_err(|err| failed("eof", err))
+    });
+
+    vec![read_line.interactive(), eof.interactive()]
+}
+
+/// The command-line arguments of a program as globals: `argc`, how many
+/// there are, and `arg(i)`, the one at index `i` counting from 0. Their types
+/// don't depend on the arguments, so programs check without them.
+pub fn arguments(args: &[String]) -> Context {
+    let args = args.to_vec();
+    let argc = args.len() as i64;
+    let arg = Native::new("arg", move |index: i64| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| args.get(index).cloned())
+            .ok_or_else(|| {
+                NativeError::new(
+                    "index out of bounds",
+                    &format!("argument {index} of a program given {argc}"),
+                )
+            })
+    })
+    .pure();
+
+    Context::from([
+        (String::from("argc"), Value::Int(argc)),
+        (String::from("arg"), Value::from(arg)),
+    ])
+}
+
+/// The functions every program can call without defining them, reading
+/// from stdin. Each call creates new closures, so they are only equal within
+/// one [`context`].
+pub fn natives() -> Vec<Native> {
+    vec![
+        Native::new("length", |str: String| str.chars().count() as i64).pure(),
+        Native::new("substring", substring).pure(),
+        Native::new("parse_int", parse_int).pure(),
+        Native::new("to_string", |value: Value| value.to_string()).pure(),
+        Native::new("abs", abs).pure(),
+        Native::new("min", |a: BigInt, b: BigInt| a.min(b)).pure(),
+        Native::new("max", |a: BigInt, b: BigInt| a.max(b)).pure(),
+    ]
+    .into_iter()
+    .chain(input(io::stdin()))
+    .collect()
+}
+
+/// Types of every global of [`context`] for the typechecker. Type variables
+/// are generalized, so `to_string` takes a value of any type.
+pub fn types() -> Vec<(&'static str, Type)> {
+    let function =
+        |parameters: Vec<Type>, result: Type| Type::Function(parameters, Box::new(result));
+
+    vec![
+        ("length", function(vec![Type::Str], Type::Int)),
+        (
+            "substring",
+            function(vec![Type::Str, Type::Int, Type::Int], Type::Str),
+        ),
+        ("parse_int", function(vec![Type::Str], Type::Int)),
+        ("to_string", function(vec![Type::Var(0)], Type::Str)),
+        ("abs", function(vec![Type::Int], Type::Int)),
+        ("min", function(vec![Type::Int, Type::Int], Type::Int)),
+        ("max", function(vec![Type::Int, Type::Int], Type::Int)),
+        ("read_line", function(Vec::new(), Type::Str)),
+        ("eof", function(Vec::new(), Type::Bool)),
+        ("argc", Type::Int),
+        ("arg", function(vec![Type::Int], Type::Str)),
+    ]
+}
+
+/// A context binding every builtin as a global, with no [`arguments`].
+pub fn context() -> Context {
+    natives()
+        .into_iter()
+        .map(|native| (native.name().to_string(), Value::from(native)))
+        .chain(arguments(&[]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::{self, Write},
+        rc::Rc,
+    };
+
+    use crate::{
+        input::{Input, Reader},
+        interpreter::{Capture, Interpreter, Output, RuntimeError, Value},
+        parser::parse,
+        typechecker::check,
+    };
+
+    use super::{arguments, context, types};
+
+    fn eval(source: &str) -> Result<Value, RuntimeError> {
+        let mut interpreter = Interpreter::with_output(Capture::new());
+
+        interpreter.eval(parse(source, "tests").unwrap().expression)
+    }
+
+    fn eval_str(source: &str) -> String {
+        eval(source).unwrap().to_string()
+    }
+
+    #[test]
+    fn every_builtin_has_a_type() {
+        let mut names: Vec<_> = context().into_keys().collect();
+        let mut typed: Vec<_> = types().iter().map(|(name, _)| name.to_string()).collect();
+        names.sort();
+        typed.sort();
+
+        assert_eq!(names, typed);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(eval_str("length(\"héllo\")"), "5");
+        assert_eq!(eval_str("substring(\"héllo\", 1, 3)"), "él");
+        assert_eq!(eval_str("substring(\"abc\", 3, 3)"), "");
+        assert_eq!(eval_str("parse_int(\"-42\") + 1"), "-41");
+        assert_eq!(eval_str("to_string((1, true)) + \"!\""), "(1, true)!");
+    }
+
+    #[test]
+    fn math() {
+        assert_eq!(eval_str("abs(-3) + abs(4)"), "7");
+        assert_eq!(eval_str("(min(3, -2), max(3, -2))"), "(-2, 3)");
+    }
+
+    #[test]
+    fn misuse_is_a_runtime_error() {
+        let cases = [
+            (
+                "substring(\"abc\", 2, 5)",
+                "index out of bounds",
+                "substring 2..5 of a string of length 3",
+            ),
+            (
+                "substring(\"abc\", -1, 2)",
+                "index out of bounds",
+                "substring -1..2 of a string of length 3",
+            ),
+            (
+                "parse_int(\"12a\")",
+                "invalid integer",
+                "\"12a\" is not an integer",
+            ),
+            (
+                "parse_int(\"99999999999999999999\")",
+                "integer overflow",
+                "99999999999999999999 does not fit in a 64-bit integer",
+            ),
+            (
+                "abs(-9223372036854775808)",
+                "integer overflow",
+                "abs(-9223372036854775808) does not fit in a 64-bit integer",
+            ),
+            (
+                "length(1)",
+                "type mismatch",
+                "argument 1: expected str, found int",
+            ),
+            (
+                "max(1, \"a\")",
+                "type mismatch",
+                "argument 2: expected int, found str",
+            ),
+        ];
+
+        for (source, message, full_text) in cases {
+            let error = eval(&format!("let x = 0; {source}")).unwrap_err();
+
+            assert_eq!(error.message, message, "{source}");
+            assert_eq!(error.full_text, full_text, "{source}");
+            assert_eq!(error.location.start, 11, "{source}");
+        }
+    }
+
+    #[test]
+    fn reads_lines() {
+        let mut interpreter =
+            Interpreter::with_output(Capture::new()).with_input(Reader::new("3\n4\n".as_bytes()));
+        let source =
+            "let a = parse_int(read_line()); let b = read_line(); (a * 2, (b, read_line()))";
+        let value = interpreter.eval(parse(source, "tests").unwrap().expression);
+
+        assert_eq!(value.unwrap().to_string(), "(6, (4, ))");
+    }
+
+    #[test]
+    fn tells_empty_lines_from_the_end() {
+        let mut interpreter =
+            Interpreter::with_output(Capture::new()).with_input(Reader::new("\n".as_bytes()));
+        let source = "let a = eof(); let b = read_line(); (a, (b, (eof(), read_line())))";
+        let value = interpreter.eval(parse(source, "tests").unwrap().expression);
+
+        assert_eq!(value.unwrap().to_string(), "(false, (, (true, )))");
+    }
+
+    /// Bytes written to the output, shared with the input reading them.
+    #[derive(Clone, Default)]
+    struct Terminal(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Terminal {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reads back what was written when the line is read.
+    impl Input for Terminal {
+        fn read_line(&mut self) -> io::Result<Option<String>> {
+            let written = String::from_utf8(self.0.borrow().clone()).unwrap();
+
+            Ok(Some(written.trim_end().to_string()))
+        }
+
+        fn at_end(&mut self) -> io::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn shows_prompts_before_reading() {
+        let terminal = Terminal::default();
+        let mut interpreter =
+            Interpreter::with_output(Output::new(terminal.clone())).with_input(terminal);
+        let source = "let _ = print(\"name?\"); read_line()";
+        let value = interpreter.eval(parse(source, "tests").unwrap().expression);
+
+        assert_eq!(value.unwrap().to_string(), "name?");
+    }
+
+    #[test]
+    fn binds_arguments() {
+        let args = [String::from("10"), String::from("2")];
+        let source = "let sum = fn (i) => { if (i == argc) { 0 } else { parse_int(arg(i)) + sum(i + 1) } }; sum(0)";
+
+        let mut interpreter = Interpreter::with_output(Capture::new());
+        for (name, value) in arguments(&args) {
+            interpreter.define(&name, value);
+        }
+        let value = interpreter.eval(parse(source, "tests").unwrap().expression);
+        assert_eq!(value.unwrap().to_string(), "12");
+        assert_eq!(eval_str(source), "0");
+        assert!(check(&parse(source, "tests").unwrap().expression).is_ok());
+
+        let error = eval("arg(0)").unwrap_err();
+        assert_eq!(error.message, "index out of bounds");
+        assert_eq!(error.full_text, "argument 0 of a program given 0");
+    }
+
+    #[test]
+    fn functions_calling_pure_builtins_are_memoized() {
+        let mut interpreter = Interpreter::with_output(Capture::new());
+        let source =
+            "let f = fn (n) => { if (n < 2) { abs(n) } else { f(n - 1) + f(n - 2) } }; f(20)";
+        let value = interpreter.eval(parse(source, "tests").unwrap().expression);
+
+        assert_eq!(value.unwrap().to_string(), "6765");
+        assert!(interpreter.cache().stats().hits > 0);
+    }
+
+    #[test]
+    fn programs_can_shadow_builtins() {
+        assert_eq!(
+            eval_str("let length = fn (s) => { 0 }; length(\"abc\")"),
+            "0"
+        );
+    }
+
+    #[test]
+    fn builtins_typecheck() {
+        let source =
+            "let s = to_string(max(1, 2)) + to_string(true); substring(s, 0, length(s) - 1)";
+        assert!(check(&parse(source, "tests").unwrap().expression).is_ok());
+
+        let errors =
+            check(&parse("length(1) + abs(\"a\")", "tests").unwrap().expression).unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+}
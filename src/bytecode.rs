@@ -0,0 +1,369 @@
+use crate::{
+    ast::{BinaryOp, Element, Function, Location, Term, Var},
+    effects,
+    interpreter::{Context, Value},
+};
+
+/// A single VM instruction. Indexes refer to tables of the [`Prototype`]
+/// being executed.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    /// Pushes `constants[index]`.
+    Constant(usize),
+    /// Pushes a local slot, failing with `names[name]` if it was never bound.
+    Load {
+        slot: usize,
+        name: usize,
+    },
+    /// Pushes a value captured by the running closure.
+    Capture(usize),
+    /// Pushes the global named `names[name]`, failing with an unbound
+    /// variable error when there is none.
+    Global(usize),
+    /// Pops the top of the stack into a local slot.
+    Store(usize),
+    /// Creates a closure of `functions[index]`, capturing from the current frame.
+    Closure(usize),
+    /// Fails with an error at `locations[location]` if the value on top of
+    /// the stack is not a closure taking `argc` arguments.
+    CheckCallable {
+        argc: usize,
+        location: usize,
+    },
+    /// Calls the closure below the `argc` arguments on top of the stack,
+    /// failing at `locations[location]` when calls are nested too deep.
+    Call {
+        argc: usize,
+        location: usize,
+    },
+    Return,
+    Jump(usize),
+    /// Pops a condition and jumps when it is false, failing at
+    /// `locations[location]` when it is not a boolean.
+    JumpIfFalse {
+        target: usize,
+        location: usize,
+    },
+    /// Jumps to `target`, keeping the left hand side on the stack, when it
+    /// alone decides the result of `&&` or `||`. Fails at
+    /// `locations[location]` when it is not a boolean.
+    ShortCircuit {
+        op: BinaryOp,
+        target: usize,
+        location: usize,
+    },
+    Binary {
+        op: BinaryOp,
+        location: usize,
+    },
+    Tuple,
+    First(usize),
+    Second(usize),
+    Print,
+}
+
+/// Where a closure gets a captured value from when it is created.
+#[derive(Debug, Clone, Copy)]
+pub enum Capture {
+    Local(usize),
+    Capture(usize),
+}
+
+/// A compiled function. The top-level program is compiled as a function
+/// without parameters.
+#[derive(Debug, Clone, Default)]
+pub struct Prototype {
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Value>,
+    pub names: Vec<Var>,
+    pub locations: Vec<Location>,
+    pub captures: Vec<Capture>,
+    pub parameters: usize,
+    pub locals: usize,
+    /// Slot holding the closure itself, for functions bound by a `let`.
+    /// Parameters take the slots right after it.
+    pub self_slot: Option<usize>,
+    pub pure: bool,
+    /// Where the function literal is defined, the whole program for `main`.
+    pub location: Location,
+    /// The id of the function literal, see [`crate::ast::Function::id`].
+    pub id: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub functions: Vec<Prototype>,
+    pub main: usize,
+}
+
+struct Scope {
+    prototype: Prototype,
+    bindings: Vec<(String, usize)>,
+    captured: Vec<String>,
+}
+
+impl Scope {
+    fn new() -> Self {
+        Self {
+            prototype: Prototype::default(),
+            bindings: Vec::new(),
+            captured: Vec::new(),
+        }
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.prototype.locals;
+        self.prototype.locals += 1;
+        self.bindings.push((name.to_string(), slot));
+
+        slot
+    }
+}
+
+enum Access {
+    Local(usize),
+    Capture(usize),
+}
+
+/// Compiles a program into bytecode for [`crate::vm`], to run with the
+/// given `globals`, which tell the natives it can memoize calls to.
+pub fn compile(term: &Term, globals: &Context) -> Program {
+    let mut term = term.clone();
+    effects::analyze(&mut term, globals);
+
+    let mut compiler = Compiler {
+        functions: Vec::new(),
+        scopes: vec![Scope::new()],
+    };
+
+    compiler.compile(&term);
+    compiler.emit(Instruction::Return);
+
+    let mut scope = compiler.scopes.pop().expect("main scope");
+    scope.prototype.location = term.location().clone();
+    compiler.functions.push(scope.prototype);
+
+    Program {
+        main: compiler.functions.len() - 1,
+        functions: compiler.functions,
+    }
+}
+
+struct Compiler {
+    functions: Vec<Prototype>,
+    scopes: Vec<Scope>,
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("compiler scope")
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        let code = &mut self.scope().prototype.code;
+        code.push(instruction);
+
+        code.len() - 1
+    }
+
+    fn location(&mut self, location: &Location) -> usize {
+        let locations = &mut self.scope().prototype.locations;
+        locations.push(location.clone());
+
+        locations.len() - 1
+    }
+
+    fn name(&mut self, var: &Var) -> usize {
+        let names = &mut self.scope().prototype.names;
+        names.push(var.clone());
+
+        names.len() - 1
+    }
+
+    fn constant(&mut self, value: Value) {
+        let constants = &mut self.scope().prototype.constants;
+        constants.push(value);
+        let index = constants.len() - 1;
+
+        self.emit(Instruction::Constant(index));
+    }
+
+    fn patch(&mut self, at: usize) {
+        let target = self.scope().prototype.code.len();
+
+        match &mut self.scope().prototype.code[at] {
+            Instruction::Jump(jump)
+            | Instruction::JumpIfFalse { target: jump, .. }
+            | Instruction::ShortCircuit { target: jump, .. } => *jump = target,
+            _ => unreachable!("only jumps can be patched"),
+        }
+    }
+
+    fn resolve(&mut self, level: usize, name: &str) -> Option<Access> {
+        let scope = &self.scopes[level];
+
+        if let Some((_, slot)) = scope.bindings.iter().rev().find(|(bound, _)| bound == name) {
+            return Some(Access::Local(*slot));
+        }
+
+        if let Some(index) = scope.captured.iter().position(|bound| bound == name) {
+            return Some(Access::Capture(index));
+        }
+
+        if level == 0 {
+            return None;
+        }
+
+        let capture = match self.resolve(level - 1, name)? {
+            Access::Local(slot) => Capture::Local(slot),
+            Access::Capture(index) => Capture::Capture(index),
+        };
+
+        let scope = &mut self.scopes[level];
+        scope.captured.push(name.to_string());
+        scope.prototype.captures.push(capture);
+
+        Some(Access::Capture(scope.captured.len() - 1))
+    }
+
+    fn compile_function(&mut self, function: &Function, self_name: Option<&str>) {
+        let mut scope = Scope::new();
+
+        // Like the tree walker, a function bound by a `let` sees itself under
+        // that name, shadowed by its own parameters.
+        if let Some(name) = self_name {
+            scope.prototype.self_slot = Some(scope.declare(name));
+        }
+
+        for parameter in &function.parameters {
+            scope.declare(&parameter.text);
+        }
+
+        scope.prototype.parameters = function.parameters.len();
+        scope.prototype.pure = function.pure;
+        scope.prototype.location = function.location.clone();
+        scope.prototype.id = function.id;
+
+        self.scopes.push(scope);
+        self.compile(&function.value);
+        self.emit(Instruction::Return);
+
+        let scope = self.scopes.pop().expect("function scope");
+        self.functions.push(scope.prototype);
+
+        let index = self.functions.len() - 1;
+        self.emit(Instruction::Closure(index));
+    }
+
+    fn compile(&mut self, term: &Term) {
+        match term {
+            Term::Int(int) => self.constant(Value::Int(int.value)),
+            Term::Str(str) => self.constant(Value::Str(str.value.clone())),
+            Term::Bool(bool) => self.constant(Value::Bool(bool.value)),
+            Term::Var(var) => {
+                let level = self.scopes.len() - 1;
+                let instruction = match self.resolve(level, &var.text) {
+                    Some(Access::Local(slot)) => Instruction::Load {
+                        slot,
+                        name: self.name(var),
+                    },
+                    Some(Access::Capture(index)) => Instruction::Capture(index),
+                    None => Instruction::Global(self.name(var)),
+                };
+
+                self.emit(instruction);
+            }
+            Term::Function(function) => self.compile_function(function, None),
+            Term::Let(let_) => {
+                match let_.value.as_ref() {
+                    Term::Function(function) => {
+                        self.compile_function(function, Some(&let_.name.text))
+                    }
+                    value => self.compile(value),
+                }
+
+                let scope = self.scope();
+                let depth = scope.bindings.len();
+                let slot = scope.declare(&let_.name.text);
+
+                self.emit(Instruction::Store(slot));
+                self.compile(&let_.next);
+                self.scope().bindings.truncate(depth);
+            }
+            Term::If(if_) => {
+                self.compile(&if_.condition);
+                let location = self.location(if_.condition.location());
+                let otherwise = self.emit(Instruction::JumpIfFalse {
+                    target: 0,
+                    location,
+                });
+
+                self.compile(&if_.then);
+                let end = self.emit(Instruction::Jump(0));
+
+                self.patch(otherwise);
+                self.compile(&if_.otherwise);
+                self.patch(end);
+            }
+            Term::Call(call) => {
+                self.compile(&call.callee);
+                let location = self.location(&call.location);
+                self.emit(Instruction::CheckCallable {
+                    argc: call.arguments.len(),
+                    location,
+                });
+
+                for argument in &call.arguments {
+                    self.compile(argument);
+                }
+
+                self.emit(Instruction::Call {
+                    argc: call.arguments.len(),
+                    location,
+                });
+            }
+            Term::Binary(binary) => {
+                self.compile(&binary.lhs);
+                let location = self.location(binary.lhs.location());
+
+                let short_circuit = match binary.op {
+                    BinaryOp::And | BinaryOp::Or => Some(self.emit(Instruction::ShortCircuit {
+                        op: binary.op.clone(),
+                        target: 0,
+                        location,
+                    })),
+                    _ => None,
+                };
+
+                self.compile(&binary.rhs);
+                self.emit(Instruction::Binary {
+                    op: binary.op.clone(),
+                    location,
+                });
+
+                if let Some(short_circuit) = short_circuit {
+                    self.patch(short_circuit);
+                }
+            }
+            Term::Tuple(tuple) => {
+                self.compile(&tuple.first);
+                self.compile(&tuple.second);
+                self.emit(Instruction::Tuple);
+            }
+            Term::First(first) => {
+                self.compile(&first.value);
+                let location = self.location(&first.location);
+                self.emit(Instruction::First(location));
+            }
+            Term::Second(second) => {
+                self.compile(&second.value);
+                let location = self.location(&second.location);
+                self.emit(Instruction::Second(location));
+            }
+            Term::Print(print) => {
+                self.compile(&print.value);
+                self.emit(Instruction::Print);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
+
+use crate::interpreter::Value;
+
+/// Key a memoized call result is stored under: the function called, its
+/// arguments and the values of the free variables its body reads.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    /// See [`crate::ast::Function::id`].
+    pub function: usize,
+    pub values: Vec<Value>,
+}
+
+/// Entries kept by [`Cache::new`].
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} evictions",
+            self.hits, self.misses, self.evictions
+        )
+    }
+}
+
+/// Memoization cache holding at most `capacity` call results, evicting the
+/// least recently used one when full. A capacity of zero disables it.
+#[derive(Debug)]
+pub struct Cache {
+    capacity: usize,
+    entries: HashMap<Key, (Value, u64)>,
+    /// Keys ordered by their last use, oldest first.
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+    stats: Stats,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn touch(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Looks up a result, marking it as the most recently used.
+    pub fn get(&mut self, key: &Key) -> Option<Value> {
+        let tick = self.touch();
+
+        match self.entries.get_mut(key) {
+            Some((value, last_used)) => {
+                let key = self.recency.remove(last_used).expect("cache recency");
+                self.recency.insert(tick, key);
+                *last_used = tick;
+                self.stats.hits += 1;
+
+                Some(value.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: Key, value: Value) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let tick = self.touch();
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(tick, key);
+
+        while self.entries.len() > self.capacity {
+            let (_, oldest) = self.recency.pop_first().expect("cache recency");
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Value;
+
+    use super::{Cache, Key, Stats};
+
+    fn key(key: &str) -> Key {
+        Key {
+            function: 0,
+            values: vec![Value::Str(key.to_string())],
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = Cache::with_capacity(2);
+        cache.insert(key("a"), Value::Int(1));
+        cache.insert(key("b"), Value::Int(2));
+
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("c"), Value::Int(3));
+
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn replacing_a_key_does_not_evict() {
+        let mut cache = Cache::with_capacity(2);
+        cache.insert(key("a"), Value::Int(1));
+        cache.insert(key("b"), Value::Int(2));
+        cache.insert(key("a"), Value::Int(3));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().evictions, 0);
+        assert_eq!(cache.get(&key("a")).unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn zero_capacity_disables_cache() {
+        let mut cache = Cache::with_capacity(0);
+        cache.insert(key("a"), Value::Int(1));
+
+        assert!(cache.get(&key("a")).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn counts_hits_misses_and_evictions() {
+        let mut cache = Cache::with_capacity(1);
+        cache.insert(key("a"), Value::Int(1));
+        cache.get(&key("a"));
+        cache.get(&key("b"));
+        cache.insert(key("b"), Value::Int(2));
+
+        assert_eq!(
+            cache.stats(),
+            Stats {
+                hits: 1,
+                misses: 1,
+                evictions: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::Write;
+
+use crate::{ast::Location, interpreter::RuntimeError, parser::ParseError};
+
+/// A secondary location shown after a diagnostic, like the definition of a
+/// function called with the wrong number of arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub message: String,
+    pub location: Location,
+}
+
+/// Anything that can be reported to the user pointing at a [`Location`].
+pub trait Diagnostic {
+    fn message(&self) -> &str;
+    fn full_text(&self) -> &str;
+    fn location(&self) -> &Location;
+
+    fn note(&self) -> Option<&Note> {
+        None
+    }
+}
+
+impl Diagnostic for RuntimeError {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn full_text(&self) -> &str {
+        &self.full_text
+    }
+
+    fn location(&self) -> &Location {
+        &self.location
+    }
+
+    fn note(&self) -> Option<&Note> {
+        self.note.as_deref()
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn full_text(&self) -> &str {
+        &self.full_text
+    }
+
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// Converts a byte offset into a 1-based `(line, column)` pair. Offsets past
+/// the end of the source are clamped to its last position.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let offset = floor_char_boundary(source, offset);
+    let before = &source[..offset];
+
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let column = source[line_start..offset].chars().count() + 1;
+
+    (line, column)
+}
+
+fn floor_char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    offset
+}
+
+/// Renders a diagnostic as a human readable report. When the `source` the
+/// location refers to is available, the offending line is printed with the
+/// span underlined, otherwise only the raw byte offsets are shown.
+pub fn render<D: Diagnostic>(diagnostic: &D, source: Option<&str>) -> String {
+    render_with(diagnostic, |_| source)
+}
+
+/// Like [`render`], for diagnostics whose locations may point into different
+/// sources, looked up by `source` for each location.
+pub fn render_with<'a, D: Diagnostic>(
+    diagnostic: &D,
+    source: impl Fn(&Location) -> Option<&'a str>,
+) -> String {
+    let mut report = format!("error: {}\n", diagnostic.message());
+
+    let location = diagnostic.location();
+    let gutter = snippet(&mut report, location, source(location));
+    let _ = writeln!(report, "{gutter} = {}", diagnostic.full_text());
+
+    if let Some(note) = diagnostic.note() {
+        let _ = writeln!(report, "note: {}", note.message);
+        snippet(&mut report, &note.location, source(&note.location));
+    }
+
+    report
+}
+
+/// Writes the location of a report, returning the gutter the lines after it
+/// are indented with.
+fn snippet(report: &mut String, location: &Location, source: Option<&str>) -> String {
+    let source = match source {
+        Some(source) => source,
+        None => {
+            let _ = writeln!(
+                report,
+                " --> {}:{}..{}",
+                location.filename, location.start, location.end
+            );
+
+            return String::from(" ");
+        }
+    };
+
+    let (line, column) = line_column(source, location.start);
+    let line_text = source.lines().nth(line - 1).unwrap_or_default();
+
+    // Spans covering several lines are underlined up to the end of the first one.
+    let (end_line, end_column) = line_column(source, location.end);
+    let width = match end_line == line {
+        true => end_column.saturating_sub(column).max(1),
+        false => (line_text.chars().count() + 1)
+            .saturating_sub(column)
+            .max(1),
+    };
+
+    let gutter = " ".repeat(line.to_string().len());
+    let padding: String = line_text
+        .chars()
+        .take(column - 1)
+        .map(|char| if char == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let _ = writeln!(report, "{gutter}--> {}:{line}:{column}", location.filename);
+    let _ = writeln!(report, "{gutter} |");
+    let _ = writeln!(report, "{line} | {line_text}");
+    let _ = writeln!(report, "{gutter} | {padding}{}", "^".repeat(width));
+
+    gutter
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Location, interpreter::RuntimeError};
+
+    use super::{line_column, render, Note};
+
+    fn error(start: usize, end: usize) -> RuntimeError {
+        RuntimeError {
+            message: String::from("invalid addition"),
+            full_text: String::from("a cannot be added to true"),
+            location: Location::new(start, end, "tests.rinha"),
+            note: None,
+        }
+    }
+
+    #[test]
+    fn line_column_first_line() {
+        assert_eq!(line_column("let x = 1;", 4), (1, 5));
+    }
+
+    #[test]
+    fn line_column_after_newlines() {
+        assert_eq!(line_column("a\nbc\nd", 3), (2, 2));
+        assert_eq!(line_column("a\nbc\nd", 5), (3, 1));
+    }
+
+    #[test]
+    fn line_column_clamps_offset() {
+        assert_eq!(line_column("ab", 100), (1, 3));
+    }
+
+    #[test]
+    fn render_underlines_span() {
+        let source = "let x = 1;\nx + true";
+        let report = render(&error(11, 19), Some(source));
+
+        assert_eq!(
+            report,
+            "error: invalid addition\n \
+             --> tests.rinha:2:1\n  \
+             |\n\
+             2 | x + true\n  \
+             | ^^^^^^^^\n  \
+             = a cannot be added to true\n"
+        );
+    }
+
+    #[test]
+    fn render_multiline_span_stops_at_line_end() {
+        let source = "if (x) {\n  1\n}";
+        let report = render(&error(0, source.len()), Some(source));
+
+        assert!(report.contains("1 | if (x) {\n  | ^^^^^^^^\n"));
+    }
+
+    #[test]
+    fn render_note() {
+        let source = "let f = fn (a) => { a };\nf(1, 2)";
+        let mut error = error(25, 32);
+        error.note = Some(Box::new(Note {
+            message: String::from("function defined here"),
+            location: Location::new(8, 23, "tests.rinha"),
+        }));
+
+        assert!(render(&error, Some(source)).ends_with(
+            "note: function defined here\n \
+             --> tests.rinha:1:9\n  \
+             |\n\
+             1 | let f = fn (a) => { a };\n  \
+             |         ^^^^^^^^^^^^^^^\n"
+        ));
+    }
+
+    #[test]
+    fn render_without_source() {
+        let report = render(&error(3, 7), None);
+
+        assert_eq!(
+            report,
+            "error: invalid addition\n --> tests.rinha:3..7\n  = a cannot be added to true\n"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    ast::Term,
+    interpreter::{Context, Value},
+};
+
+/// What a call inside a function body may run.
+#[derive(Debug, Clone, Copy)]
+enum Callee {
+    /// A function literal, identified by its position in the program.
+    Known(usize),
+    /// A global native function without effects.
+    Native,
+    /// A parameter, another global or a computed closure.
+    Unknown,
+}
+
+/// Effects of a function body, not counting the functions it defines.
+#[derive(Debug, Default)]
+struct Effects {
+    prints: bool,
+    calls: Vec<Callee>,
+}
+
+/// Marks every function of a program as pure when calling it can never
+/// print, directly or through the functions it calls. Calls to closures
+/// that can't be traced back to a function literal, like parameters, are
+/// assumed to be impure, unless they are pure natives of the `globals` the
+/// program runs with. Every function also gets a new id, so results
+/// memoized for other programs are never mistaken for its own.
+pub fn analyze(term: &mut Term, globals: &Context) {
+    let mut analyzer = Analyzer {
+        functions: Vec::new(),
+        bindings: Vec::new(),
+        globals,
+    };
+    analyzer.visit(term, None);
+
+    // Start from every function being pure and remove the ones that print or
+    // call impure functions, until nothing changes. Recursive functions stay
+    // pure unless something in the cycle prints.
+    let mut pure: Vec<bool> = analyzer.functions.iter().map(|f| !f.prints).collect();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for (index, effects) in analyzer.functions.iter().enumerate() {
+            let calls_impure = effects.calls.iter().any(|callee| match callee {
+                Callee::Known(callee) => !pure[*callee],
+                Callee::Native => false,
+                Callee::Unknown => true,
+            });
+
+            if pure[index] && calls_impure {
+                pure[index] = false;
+                changed = true;
+            }
+        }
+    }
+
+    annotate(term, &pure, &mut 0);
+}
+
+struct Analyzer<'a> {
+    functions: Vec<Effects>,
+    /// Names in scope, with the function literal they are bound to.
+    bindings: Vec<(String, Option<usize>)>,
+    globals: &'a Context,
+}
+
+impl Analyzer<'_> {
+    fn lookup(&self, name: &str) -> Callee {
+        match self.bindings.iter().rev().find(|(bound, _)| bound == name) {
+            Some((_, Some(function))) => Callee::Known(*function),
+            Some((_, None)) => Callee::Unknown,
+            None => match self.globals.get(name) {
+                Some(Value::Native(native)) if native.is_pure() => Callee::Native,
+                _ => Callee::Unknown,
+            },
+        }
+    }
+
+    /// Visits `term`, recording effects on the function at index `current`.
+    /// Returns the index of the function literal `term` evaluates to, if any.
+    fn visit(&mut self, term: &Term, current: Option<usize>) -> Option<usize> {
+        match term {
+            Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) => None,
+            Term::Function(function) => {
+                let index = self.functions.len();
+                self.functions.push(Effects::default());
+
+                let scope = self.bindings.len();
+                for parameter in &function.parameters {
+                    self.bindings.push((parameter.text.clone(), None));
+                }
+                self.visit(&function.value, Some(index));
+                self.bindings.truncate(scope);
+
+                Some(index)
+            }
+            Term::Let(let_) => {
+                let scope = self.bindings.len();
+
+                match *let_.value {
+                    // Functions bound by a let can call themselves.
+                    Term::Function(_) => {
+                        let index = self.functions.len();
+                        self.bindings.push((let_.name.text.clone(), Some(index)));
+                        self.visit(&let_.value, current);
+                    }
+                    _ => {
+                        let function = self.visit(&let_.value, current);
+                        self.bindings.push((let_.name.text.clone(), function));
+                    }
+                }
+
+                self.visit(&let_.next, current);
+                self.bindings.truncate(scope);
+
+                None
+            }
+            Term::Call(call) => {
+                let callee = match call.callee.as_ref() {
+                    Term::Var(var) => self.lookup(&var.text),
+                    callee => self
+                        .visit(callee, current)
+                        .map_or(Callee::Unknown, Callee::Known),
+                };
+
+                if let Some(current) = current {
+                    self.functions[current].calls.push(callee);
+                }
+
+                for argument in &call.arguments {
+                    self.visit(argument, current);
+                }
+
+                None
+            }
+            Term::Print(print) => {
+                if let Some(current) = current {
+                    self.functions[current].prints = true;
+                }
+
+                self.visit(&print.value, current);
+                None
+            }
+            Term::If(if_) => {
+                self.visit(&if_.condition, current);
+                self.visit(&if_.then, current);
+                self.visit(&if_.otherwise, current);
+                None
+            }
+            Term::Binary(binary) => {
+                self.visit(&binary.lhs, current);
+                self.visit(&binary.rhs, current);
+                None
+            }
+            Term::Tuple(tuple) => {
+                self.visit(&tuple.first, current);
+                self.visit(&tuple.second, current);
+                None
+            }
+            Term::First(first) => {
+                self.visit(&first.value, current);
+                None
+            }
+            Term::Second(second) => {
+                self.visit(&second.value, current);
+                None
+            }
+        }
+    }
+}
+
+/// Writes the analysis back, numbering functions in the same order as
+/// [`Analyzer::visit`].
+fn annotate(term: &mut Term, pure: &[bool], next: &mut usize) {
+    match term {
+        Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) => {}
+        Term::Function(function) => {
+            static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+            // Only copied when the term shares the function with another.
+            let function = Rc::make_mut(function);
+            function.pure = pure[*next];
+            function.id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            *next += 1;
+
+            annotate(&mut function.value, pure, next);
+        }
+        Term::Let(let_) => {
+            annotate(&mut let_.value, pure, next);
+            annotate(&mut let_.next, pure, next);
+        }
+        Term::Call(call) => {
+            annotate(&mut call.callee, pure, next);
+            for argument in &mut call.arguments {
+                annotate(argument, pure, next);
+            }
+        }
+        Term::Print(print) => annotate(&mut print.value, pure, next),
+        Term::If(if_) => {
+            annotate(&mut if_.condition, pure, next);
+            annotate(&mut if_.then, pure, next);
+            annotate(&mut if_.otherwise, pure, next);
+        }
+        Term::Binary(binary) => {
+            annotate(&mut binary.lhs, pure, next);
+            annotate(&mut binary.rhs, pure, next);
+        }
+        Term::Tuple(tuple) => {
+            annotate(&mut tuple.first, pure, next);
+            annotate(&mut tuple.second, pure, next);
+        }
+        Term::First(first) => annotate(&mut first.value, pure, next),
+        Term::Second(second) => annotate(&mut second.value, pure, next),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Term, builtins, interpreter::Context, parser::parse};
+
+    use super::analyze;
+
+    /// Purity of every function in the program, in source order, with the
+    /// builtins as globals.
+    fn purity(source: &str) -> Vec<bool> {
+        fn collect(term: &Term, pure: &mut Vec<bool>) {
+            match term {
+                Term::Function(function) => {
+                    pure.push(function.pure);
+                    collect(&function.value, pure);
+                }
+                Term::Let(let_) => {
+                    collect(&let_.value, pure);
+                    collect(&let_.next, pure);
+                }
+                Term::Call(call) => {
+                    collect(&call.callee, pure);
+                    call.arguments
+                        .iter()
+                        .for_each(|argument| collect(argument, pure));
+                }
+                Term::If(if_) => {
+                    collect(&if_.condition, pure);
+                    collect(&if_.then, pure);
+                    collect(&if_.otherwise, pure);
+                }
+                Term::Print(print) => collect(&print.value, pure),
+                Term::Binary(binary) => {
+                    collect(&binary.lhs, pure);
+                    collect(&binary.rhs, pure);
+                }
+                Term::Tuple(tuple) => {
+                    collect(&tuple.first, pure);
+                    collect(&tuple.second, pure);
+                }
+                Term::First(first) => collect(&first.value, pure),
+                Term::Second(second) => collect(&second.value, pure),
+                Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) => {}
+            }
+        }
+
+        let mut term = parse(source, "tests").unwrap().expression;
+        analyze(&mut term, &builtins::context());
+
+        let mut pure = Vec::new();
+        collect(&term, &mut pure);
+
+        pure
+    }
+
+    #[test]
+    fn recursive_function_is_pure() {
+        assert_eq!(purity(include_str!("../examples/fib.rinha")), [true]);
+    }
+
+    #[test]
+    fn print_in_let_value() {
+        assert_eq!(purity("fn (n) => { let _ = print(1); n }"), [false]);
+    }
+
+    #[test]
+    fn print_in_branch() {
+        assert_eq!(
+            purity("fn (c, x) => { if (c) { print(x) } else { 0 } }"),
+            [false]
+        );
+    }
+
+    #[test]
+    fn defining_a_printing_closure_is_pure() {
+        assert_eq!(purity("fn (x) => { fn () => { print(x) } }"), [true, false]);
+    }
+
+    #[test]
+    fn calling_impure_function_is_impure() {
+        let source = "let log = fn (x) => { print(x) }; let f = fn (n) => { log(n) + 1 }; f";
+
+        assert_eq!(purity(source), [false, false]);
+    }
+
+    #[test]
+    fn impurity_propagates_through_recursion() {
+        let source = "let f = fn (n) => { if (n == 0) { print(0) } else { let g = fn () => { f(n - 1) }; g() } }; f";
+
+        assert_eq!(purity(source), [false, false]);
+    }
+
+    #[test]
+    fn calling_parameters_is_impure() {
+        assert_eq!(purity("fn (f, x) => { f(x) }"), [false]);
+    }
+
+    #[test]
+    fn calling_pure_known_function_is_pure() {
+        let source =
+            "let double = fn (x) => { x * 2 }; fn (n) => { double(n) + (fn (m) => { m })(1) }";
+
+        assert_eq!(purity(source), [true, true, true]);
+    }
+
+    #[test]
+    fn calling_pure_natives_is_pure() {
+        let source = "fn (n) => { abs(n) + length(to_string(n)) }";
+
+        assert_eq!(purity(source), [true]);
+        assert_eq!(purity("fn () => { read_line() }"), [false]);
+    }
+
+    #[test]
+    fn shadowed_natives_are_not_trusted() {
+        let source = "fn (abs) => { abs(1) }";
+
+        assert_eq!(purity(source), [false]);
+
+        let mut term = parse("fn (n) => { abs(n) }", "tests").unwrap().expression;
+        analyze(&mut term, &Context::new());
+        assert!(matches!(term, Term::Function(function) if !function.pure));
+    }
+}
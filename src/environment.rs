@@ -0,0 +1,97 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    ast::Address,
+    interpreter::{Options, Value},
+    profiler::Profiler,
+};
+
+/// The local slots of a function call, linked to the frame the function
+/// was defined in. Closures keep a reference to their defining frame, so
+/// creating and calling them never copies the enclosing scope.
+#[derive(Debug, Default)]
+pub struct Frame {
+    slots: RefCell<Vec<Option<Value>>>,
+    parent: Option<Env>,
+    /// Number of calls running when this frame's body is evaluated.
+    depth: usize,
+    /// Inherited from the root frame.
+    options: Options,
+    /// Inherited from the root frame, when profiling.
+    profiler: Option<Profiler>,
+    /// Position of the native stack when the root frame was created.
+    stack_base: usize,
+}
+
+/// Position of the top of the native stack, as the address of a local.
+fn stack_position() -> usize {
+    let marker = 0u8;
+
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+pub type Env = Rc<Frame>;
+
+impl Frame {
+    /// Creates the frame of the top-level program, evaluated with `options`
+    /// and profiled by `profiler`, if any.
+    pub fn root(options: Options, profiler: Option<Profiler>) -> Env {
+        Rc::new(Frame {
+            options,
+            profiler,
+            stack_base: stack_position(),
+            ..Frame::default()
+        })
+    }
+
+    /// Creates a frame for a call at the given depth, starting with the
+    /// given slots.
+    pub fn child(parent: &Env, slots: Vec<Option<Value>>, depth: usize) -> Env {
+        Rc::new(Frame {
+            slots: RefCell::new(slots),
+            parent: Some(parent.clone()),
+            depth,
+            options: parent.options,
+            profiler: parent.profiler.clone(),
+            stack_base: parent.stack_base,
+        })
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Bytes of native stack used since the root frame was created, to stop
+    /// evaluations nesting deeper than the stack they were given.
+    pub fn stack_used(&self) -> usize {
+        self.stack_base.abs_diff(stack_position())
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Looks up a resolved variable, returning `None` if its slot was
+    /// never assigned.
+    pub fn get(&self, address: &Address) -> Option<Value> {
+        let mut frame = self;
+        for _ in 0..address.depth {
+            frame = frame.parent.as_ref()?;
+        }
+
+        frame.slots.borrow().get(address.slot).cloned().flatten()
+    }
+
+    pub fn set(&self, slot: usize, value: Value) {
+        let mut slots = self.slots.borrow_mut();
+        if slots.len() <= slot {
+            slots.resize(slot + 1, None);
+        }
+
+        slots[slot] = Some(value);
+    }
+}
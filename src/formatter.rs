@@ -0,0 +1,246 @@
+use crate::ast::{Binary, Term};
+
+/// Spaces each level of blocks is indented by.
+const INDENT: &str = "  ";
+
+/// Renders a term as canonical rinha source, ending with a newline. Blocks
+/// always span several lines, everything else is kept on one, and
+/// parentheses are only added where precedence requires them.
+pub fn format(term: &Term) -> String {
+    let mut formatter = Formatter {
+        output: String::new(),
+        indent: 0,
+    };
+
+    formatter.term(term);
+    formatter.output.push('\n');
+
+    formatter.output
+}
+
+/// Writes a string literal, escaping what the lexer unescapes.
+fn quote(output: &mut String, value: &str) {
+    output.push('"');
+    for char in value.chars() {
+        match char {
+            '\n' => output.push_str("\\n"),
+            '\t' => output.push_str("\\t"),
+            '\r' => output.push_str("\\r"),
+            '\0' => output.push_str("\\0"),
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            char => output.push(char),
+        }
+    }
+    output.push('"');
+}
+
+struct Formatter {
+    output: String,
+    indent: usize,
+}
+
+impl Formatter {
+    fn newline(&mut self) {
+        self.output.push('\n');
+        self.output.push_str(&INDENT.repeat(self.indent));
+    }
+
+    /// Writes `{`, the term on its own indented lines, and `}`.
+    fn block(&mut self, term: &Term) {
+        self.output.push('{');
+        self.indent += 1;
+        self.newline();
+        self.term(term);
+        self.indent -= 1;
+        self.newline();
+        self.output.push('}');
+    }
+
+    fn list<'a>(&mut self, terms: impl IntoIterator<Item = &'a Term>) {
+        for (index, term) in terms.into_iter().enumerate() {
+            if index > 0 {
+                self.output.push_str(", ");
+            }
+            self.term(term);
+        }
+    }
+
+    /// Writes a term wrapped in parentheses when `parenthesize` is true.
+    fn group(&mut self, term: &Term, parenthesize: bool) {
+        if parenthesize {
+            self.output.push('(');
+        }
+        self.term(term);
+        if parenthesize {
+            self.output.push(')');
+        }
+    }
+
+    /// Writes `keyword(term)`, used by `print`, `first` and `second`.
+    fn unary(&mut self, keyword: &str, term: &Term) {
+        self.output.push_str(keyword);
+        self.output.push('(');
+        self.term(term);
+        self.output.push(')');
+    }
+
+    fn binary(&mut self, binary: &Binary) {
+        let precedence = binary.op.precedence();
+
+        // Operators associate to the left, so an operand of the same
+        // precedence only needs parentheses on the right.
+        let lhs = match binary.lhs.as_ref() {
+            Term::Binary(lhs) => lhs.op.precedence() < precedence,
+            term => matches!(term, Term::Let(_)),
+        };
+        let rhs = match binary.rhs.as_ref() {
+            Term::Binary(rhs) => rhs.op.precedence() <= precedence,
+            term => matches!(term, Term::Let(_)),
+        };
+
+        self.group(&binary.lhs, lhs);
+        self.output.push(' ');
+        self.output.push_str(binary.op.symbol());
+        self.output.push(' ');
+        self.group(&binary.rhs, rhs);
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::Int(int) => self.output.push_str(&int.value.to_string()),
+            Term::Str(str) => quote(&mut self.output, &str.value),
+            Term::Bool(bool) => self.output.push_str(&bool.value.to_string()),
+            Term::Var(var) => self.output.push_str(&var.text),
+            Term::Call(call) => {
+                let callee = matches!(call.callee.as_ref(), Term::Binary(_) | Term::Let(_));
+                self.group(&call.callee, callee);
+
+                self.output.push('(');
+                self.list(&call.arguments);
+                self.output.push(')');
+            }
+            Term::Binary(binary) => self.binary(binary),
+            Term::Function(function) => {
+                self.output.push_str("fn (");
+                for (index, parameter) in function.parameters.iter().enumerate() {
+                    if index > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.output.push_str(&parameter.text);
+                }
+                self.output.push_str(") => ");
+                self.block(&function.value);
+            }
+            Term::Let(let_) => {
+                self.output.push_str("let ");
+                self.output.push_str(&let_.name.text);
+                self.output.push_str(" = ");
+
+                let start = self.output.len();
+                self.term(&let_.value);
+                self.output.push(';');
+
+                // Bindings spanning several lines, like functions, are
+                // separated from what follows by a blank line.
+                if self.output[start..].contains('\n') {
+                    self.output.push('\n');
+                }
+                self.newline();
+                self.term(&let_.next);
+            }
+            Term::If(if_) => {
+                self.output.push_str("if (");
+                self.term(&if_.condition);
+                self.output.push_str(") ");
+                self.block(&if_.then);
+                self.output.push_str(" else ");
+                self.block(&if_.otherwise);
+            }
+            Term::Print(print) => self.unary("print", &print.value),
+            Term::First(first) => self.unary("first", &first.value),
+            Term::Second(second) => self.unary("second", &second.value),
+            Term::Tuple(tuple) => {
+                self.output.push('(');
+                self.list([tuple.first.as_ref(), tuple.second.as_ref()]);
+                self.output.push(')');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+
+    use super::format;
+
+    fn reformat(source: &str) -> String {
+        format(&parse(source, "tests").unwrap().expression)
+    }
+
+    #[test]
+    fn canonical_source_is_unchanged() {
+        let source = include_str!("../examples/fib.rinha");
+
+        assert_eq!(reformat(source), source);
+    }
+
+    #[test]
+    fn indents_blocks() {
+        assert_eq!(
+            reformat("let sum = fn (n) => { if (n == 1) { n } else { n + sum(n - 1) } }; print (sum(5))"),
+            "let sum = fn (n) => {\n  if (n == 1) {\n    n\n  } else {\n    n + sum(n - 1)\n  }\n};\n\nprint(sum(5))\n"
+        );
+        assert_eq!(
+            reformat("let a = 1; let b = (a, \"b\"); first(b)"),
+            "let a = 1;\nlet b = (a, \"b\");\nfirst(b)\n"
+        );
+    }
+
+    #[test]
+    fn parenthesizes_by_precedence() {
+        let cases = [
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 + (2 * 3)", "1 + 2 * 3"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(a || b) && c", "(a || b) && c"),
+            ("a || (b && c)", "a || b && c"),
+            ("(1 < 2) == true", "1 < 2 == true"),
+            ("1 - -2", "1 - -2"),
+            ("(f + g)(1)", "(f + g)(1)"),
+            ("1 + (let x = 2; x)", "1 + (let x = 2;\nx)"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(reformat(source), format!("{expected}\n"), "{source}");
+        }
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(
+            reformat(r#"print("a\n\t\"b\"\\")"#),
+            "print(\"a\\n\\t\\\"b\\\"\\\\\")\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            include_str!("../examples/combination.rinha"),
+            include_str!("../examples/sum.rinha"),
+            include_str!("../examples/hello-world.rinha"),
+            "let f = fn () => { let x = fn (a, b) => { a }; x(1, 2) }; f()",
+            "let t = (if (true) { 1 } else { 2 }, fn (x) => { x }); second(t)(first(t) + 1)",
+            "let x = let y = 1; y; x",
+        ];
+
+        for source in sources {
+            let formatted = reformat(source);
+
+            assert_eq!(reformat(&formatted), formatted, "{source}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{self, BufRead, Stdin};
+
+/// Where the lines of `read_line` come from, the counterpart of
+/// [`crate::output::Printer`].
+pub trait Input {
+    /// Reads the next line without its line ending, or `None` at the end of
+    /// the input.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    /// Whether the input is over, waiting for more of it if needed.
+    fn at_end(&mut self) -> io::Result<bool>;
+}
+
+/// Removes the line ending `BufRead::read_line` leaves in.
+fn line(mut line: String, read: usize) -> Option<String> {
+    if read == 0 {
+        return None;
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+
+    Some(line)
+}
+
+/// Stdin is only locked while a line is read, so it can be shared with
+/// whatever else reads it, like the REPL.
+impl Input for Stdin {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut text = String::new();
+        let read = self.lock().read_line(&mut text)?;
+
+        Ok(line(text, read))
+    }
+
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.lock().fill_buf()?.is_empty())
+    }
+}
+
+/// Reads lines from a [`BufRead`], like a file or a string for tests.
+pub struct Reader<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: BufRead> Input for Reader<R> {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut text = String::new();
+        let read = self.reader.read_line(&mut text)?;
+
+        Ok(line(text, read))
+    }
+
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.reader.fill_buf()?.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, Reader};
+
+    #[test]
+    fn reads_lines_without_their_endings() {
+        let mut input = Reader::new("a\r\n\nb".as_bytes());
+
+        assert_eq!(input.read_line().unwrap().as_deref(), Some("a"));
+        assert_eq!(input.read_line().unwrap().as_deref(), Some(""));
+        assert_eq!(input.read_line().unwrap().as_deref(), Some("b"));
+        assert!(input.at_end().unwrap());
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+}
@@ -1,26 +1,68 @@
 use std::{
-    cell::RefCell,
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::HashMap,
     fmt::Display,
     hash::{Hash, Hasher},
+    io::{self, Stdout},
     rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::ast::{
-    Binary, Call, Element, First, Function, If, Let, Location, Print, Second, Term, Var,
+use num_bigint::BigInt;
+
+use crate::{
+    ast::{Binary, Call, Element, First, Function, If, Let, Location, Print, Second, Term, Var},
+    binary::Overflow,
+    builtins,
+    diagnostic::Note,
+    effects,
+    environment::{Env, Frame},
+    input::Input,
+    native::{Native, NativeFn},
+    profiler::{self, Profiler},
+    resolver, vm,
+};
+
+pub use crate::{
+    cache::{Cache, Key},
+    output::{Capture, Output, Printer},
 };
 
 #[derive(Clone, Debug)]
 pub struct Closure {
-    parameters: Vec<Var>,
-    body: Box<Term>,
-    context: Rc<RefCell<Context>>,
+    /// Identifies this closure for memoization, as closures of the same
+    /// function may capture different values.
+    id: usize,
+    function: Rc<Function>,
+    env: Env,
 }
 
-#[derive(Clone, Debug)]
+/// Returns a new, never reused, closure identifier.
+pub(crate) fn next_closure_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Closures capture an environment that can't be compared, so a closure is
+/// only equal to itself.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Closure {}
+
+impl Hash for Closure {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Tuple {
-    first: Box<Value>,
-    second: Box<Value>,
+    pub(crate) first: Box<Value>,
+    pub(crate) second: Box<Value>,
 }
 
 impl Display for Tuple {
@@ -32,40 +74,33 @@ impl Display for Tuple {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Values compare and hash structurally, so they can be used directly as
+/// memoization keys.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Value {
     Closure(Closure),
+    Compiled(Rc<vm::Closure>),
+    /// A function implemented in Rust, see [`Interpreter::register`].
+    Native(Rc<Native>),
     Int(i64),
+    /// An integer that doesn't fit in an `i64`, only created with
+    /// [`Overflow::Promote`].
+    BigInt(Rc<BigInt>),
     Str(String),
     Bool(bool),
     Tuple(Tuple),
 }
 
-impl Hash for Value {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        match self {
-            Self::Closure(_closure) => panic!("this should never be executed"),
-            Self::Int(int) => format!("Int({int})").hash(state),
-            Self::Str(string) => format!("Str({string})").hash(state),
-            Self::Bool(bool) => format!("Bool({bool})").hash(state),
-            Self::Tuple(tuple) => format!("Tuple({tuple})").hash(state),
-        }
-    }
-}
-
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let value = match self {
-            Self::Closure(_closure) => String::from("[closure]"),
+            Self::Closure(_) | Self::Compiled(_) | Self::Native(_) => String::from("[closure]"),
             Self::Int(int) => int.to_string(),
+            Self::BigInt(int) => int.to_string(),
             Self::Str(str) => str.to_string(),
             Self::Bool(bool) => bool.to_string(),
             Self::Tuple(tuple) => {
-                format!(
-                    "({}, {})",
-                    tuple.first.to_string(),
-                    tuple.second.to_string()
-                )
+                format!("({}, {})", tuple.first, tuple.second)
             }
         };
 
@@ -73,7 +108,20 @@ impl Display for Value {
     }
 }
 
-pub type Cache = std::collections::HashMap<String, Value>;
+impl Value {
+    /// Name of the type of the value, as the typechecker writes it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Closure(_) | Self::Compiled(_) | Self::Native(_) => "function",
+            Self::Int(_) | Self::BigInt(_) => "int",
+            Self::Str(_) => "str",
+            Self::Bool(_) => "bool",
+            Self::Tuple(_) => "tuple",
+        }
+    }
+}
+
+/// Global bindings, for variables the resolver couldn't bind lexically.
 pub type Context = HashMap<String, Value>;
 
 #[derive(Debug, Clone)]
@@ -81,119 +129,173 @@ pub struct RuntimeError {
     pub message: String,
     pub full_text: String,
     pub location: Location,
+    pub note: Option<Box<Note>>,
+}
+
+/// The result of evaluating a term in tail position. Calls are returned
+/// instead of evaluated, so [`eval_term`] can run them without growing the
+/// native stack.
+enum Tail {
+    Value(Value),
+    /// Evaluate the body of a closure in the frame of its arguments,
+    /// memoizing the result under the key when there is one.
+    Call(Closure, Env, Option<Key>),
 }
 
-fn eval_let<I: Printer>(
-    let_: Let,
+/// Binds the value of a let, returning the term to evaluate next.
+fn eval_let<'a, I: Printer>(
+    let_: &'a Let,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
-) -> Result<Value, RuntimeError> {
-    let name = let_.name.text;
+) -> Result<&'a Term, RuntimeError> {
+    let value = eval_term(&let_.value, env, context, cache, io)?;
 
-    match eval(let_.value, context, cache, io)? {
-        Value::Closure(closure) => {
-            let self_ = Value::Closure(Closure {
-                parameters: closure.parameters,
-                body: closure.body,
-                context: closure.context.clone(),
-            });
-
-            closure
-                .context
-                .borrow_mut()
-                .insert(name.clone(), self_.clone());
-
-            context.insert(name, self_.clone());
-        }
-        value => {
-            context.insert(name, value);
+    match let_.name.address {
+        Some(address) => env.set(address.slot, value),
+        None => {
+            context.insert(let_.name.text.clone(), value);
         }
     }
 
-    eval(let_.next, context, cache, io)
+    Ok(&let_.next)
 }
 
-fn cache_key(body: &Box<Term>, arguments: Vec<Value>) -> Option<String> {
-    let arguments = arguments
-        .into_iter()
-        .map(|argument| match argument {
-            Value::Closure(_) => None,
-            value => {
-                let mut s = DefaultHasher::new();
-                // TODO: is ok to define the hasher on each iteration?
-                value.hash(&mut s);
-                Some(s.finish().to_string())
-            }
-        })
-        .collect::<Option<Vec<String>>>()?;
+/// Builds the memoization key of a call: the function, the arguments and
+/// the current values of the free variables the body reads.
+fn cache_key(closure: &Closure, mut values: Vec<Value>, context: &Context) -> Option<Key> {
+    for free in &closure.function.free {
+        let value = match &free.address {
+            Some(address) => closure.env.get(address),
+            None => context.get(&free.text).cloned(),
+        };
 
-    let mut s = DefaultHasher::new();
-    (*body.clone(), arguments).hash(&mut s);
+        // An unbound free variable makes the call fail, so it isn't cached.
+        values.push(value?);
+    }
 
-    Some(s.finish().to_string())
+    Some(Key {
+        function: closure.function.id,
+        values,
+    })
 }
 
-fn eval_memo<I: Printer>(
-    body: Box<Term>,
-    arguments: Vec<Value>,
-    context: &mut Context,
-    cache: &mut Cache,
-    io: &mut I,
-) -> Result<Value, RuntimeError> {
-    match cache_key(&body, arguments.clone()) {
-        Some(cache_key) => match cache.get(&cache_key) {
-            Some(cached_value) => Ok(cached_value.clone()),
-            None => {
-                let value = eval(body, context, cache, io)?;
-                cache.insert(cache_key, value.clone());
-
-                Ok(value)
-            }
-        },
-        None => eval(body, context, cache, io),
+/// Error for a call nested deeper than the configured maximum depth.
+pub(crate) fn stack_overflow(max_depth: usize, location: &Location) -> RuntimeError {
+    RuntimeError {
+        message: String::from("stack overflow"),
+        full_text: format!("call nested deeper than the maximum depth of {max_depth}"),
+        location: location.clone(),
+        note: None,
+    }
+}
+
+/// Error for a call whose number of arguments doesn't match the parameters
+/// of the function defined at `function`, if it is defined in rinha.
+pub(crate) fn arity_mismatch(
+    parameters: usize,
+    arguments: usize,
+    call: &Location,
+    function: Option<&Location>,
+) -> RuntimeError {
+    RuntimeError {
+        message: String::from("arity mismatch"),
+        full_text: format!("function expects {parameters} arguments but {arguments} were given"),
+        location: call.clone(),
+        note: function.map(|function| {
+            Box::new(Note {
+                message: String::from("function defined here"),
+                location: function.clone(),
+            })
+        }),
     }
 }
 
+/// Evaluates the callee and arguments of a call running at `depth`. There is
+/// no partial application: the arguments must match the parameters of the
+/// callee, which is checked before evaluating them.
 fn eval_call<I: Printer>(
-    call: Call,
+    call: &Call,
+    env: &Env,
+    depth: usize,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
-) -> Result<Value, RuntimeError> {
-    match eval(call.callee, context, cache, io)? {
+) -> Result<Tail, RuntimeError> {
+    match eval_term(&call.callee, env, context, cache, io)? {
         Value::Closure(closure) => {
-            let mut new_context = closure.context.borrow_mut().clone();
+            let parameters = closure.function.parameters.len();
+            if parameters != call.arguments.len() {
+                return Err(arity_mismatch(
+                    parameters,
+                    call.arguments.len(),
+                    &call.location,
+                    Some(&closure.function.location),
+                ));
+            }
+
             let mut arguments = Vec::new();
+            for argument in &call.arguments {
+                arguments.push(eval_term(argument, env, context, cache, io)?);
+            }
+
+            if depth > env.options().max_depth {
+                return Err(stack_overflow(env.options().max_depth, &call.location));
+            }
 
-            for (parameter, argument) in closure.parameters.clone().into_iter().zip(call.arguments)
-            {
-                let argument = eval(Box::new(argument), context, cache, io)?;
-                arguments.push(argument.clone());
+            let memo = match closure.function.pure {
+                true => cache_key(&closure, arguments.clone(), context),
+                false => None,
+            };
+            let arguments = arguments.into_iter().map(Some).collect();
+            let new_env = Frame::child(&closure.env, arguments, depth);
 
-                new_context.insert(parameter.text, argument);
+            Ok(Tail::Call(closure, new_env, memo))
+        }
+        Value::Native(native) => {
+            if native.arity() != call.arguments.len() {
+                return Err(arity_mismatch(
+                    native.arity(),
+                    call.arguments.len(),
+                    &call.location,
+                    None,
+                ));
             }
 
-            match closure.body.is_pure() {
-                true => eval_memo(closure.body, arguments, &mut new_context, cache, io),
-                false => eval(closure.body, &mut new_context, cache, io),
+            let mut arguments = Vec::new();
+            for argument in &call.arguments {
+                arguments.push(eval_term(argument, env, context, cache, io)?);
             }
+
+            if native.is_interactive() {
+                io.show();
+            }
+
+            let value = native
+                .call(arguments)
+                .map_err(|err| err.at(&call.location))?;
+
+            Ok(Tail::Value(value))
         }
         value => Err(RuntimeError {
             message: String::from("invalid function call"),
             full_text: format!("{} cannot be called as a function", value),
-            location: call.location,
+            location: call.location.clone(),
+            note: None,
         }),
     }
 }
 
-fn eval_if<I: Printer>(
-    if_: If,
+/// Checks the condition of an if, returning the branch to evaluate next.
+fn eval_if<'a, I: Printer>(
+    if_: &'a If,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
-) -> Result<Value, RuntimeError> {
-    let condition_result = eval(if_.condition.clone(), context, cache, io)?;
+) -> Result<&'a Term, RuntimeError> {
+    let condition_result = eval_term(&if_.condition, env, context, cache, io)?;
     let condition = match condition_result {
         Value::Bool(bool) => Ok(bool),
         _ => Err(RuntimeError {
@@ -203,49 +305,59 @@ fn eval_if<I: Printer>(
                 condition_result
             ),
             location: if_.condition.location().clone(),
+            note: None,
         }),
     }?;
 
     match condition {
-        true => eval(if_.then, context, cache, io),
-        false => eval(if_.otherwise, context, cache, io),
+        true => Ok(&if_.then),
+        false => Ok(&if_.otherwise),
     }
 }
 
 fn eval_binary<I: Printer>(
-    binary: Binary,
+    binary: &Binary,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
 ) -> Result<Value, RuntimeError> {
-    let lhs = eval(binary.lhs.clone(), context, cache, io)?;
-    let rhs = eval(binary.rhs.clone(), context, cache, io)?;
+    let lhs = eval_term(&binary.lhs, env, context, cache, io)?;
+    if let Some(value) = lhs.short_circuit(&binary.op, binary.lhs.location())? {
+        return Ok(value);
+    }
+
+    let rhs = eval_term(&binary.rhs, env, context, cache, io)?;
 
-    lhs.binary_op(binary, rhs)
+    lhs.binary_op(binary, &rhs, env.options().overflow)
 }
 
-fn eval_var(var: Var, context: &mut Context) -> Result<Value, RuntimeError> {
-    context
-        .get(&var.text)
-        .ok_or(RuntimeError {
-            message: format!("unbound variable \"{}\"", var.text),
-            full_text: format!(
-                "variable \"{}\" was not defined in the current scope",
-                var.text
-            ),
-            location: var.location,
-        })
-        .map(|value| value.clone())
+fn eval_var(var: &Var, env: &Env, context: &mut Context) -> Result<Value, RuntimeError> {
+    let value = match &var.address {
+        Some(address) => env.get(address),
+        None => context.get(&var.text).cloned(),
+    };
+
+    value.ok_or(RuntimeError {
+        message: format!("unbound variable \"{}\"", var.text),
+        full_text: format!(
+            "variable \"{}\" was not defined in the current scope",
+            var.text
+        ),
+        location: var.location.clone(),
+        note: None,
+    })
 }
 
 fn eval_tuple<I: Printer>(
-    tuple: crate::ast::Tuple,
+    tuple: &crate::ast::Tuple,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
 ) -> Result<Value, RuntimeError> {
-    let first = eval(tuple.first, context, cache, io)?;
-    let second = eval(tuple.second, context, cache, io)?;
+    let first = eval_term(&tuple.first, env, context, cache, io)?;
+    let second = eval_term(&tuple.second, env, context, cache, io)?;
 
     Ok(Value::Tuple(Tuple {
         first: Box::new(first),
@@ -254,99 +366,356 @@ fn eval_tuple<I: Printer>(
 }
 
 fn eval_first<I: Printer>(
-    first: First,
+    first: &First,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
 ) -> Result<Value, RuntimeError> {
-    match eval(first.value, context, cache, io)? {
+    match eval_term(&first.value, env, context, cache, io)? {
         Value::Tuple(Tuple { first, second: _ }) => Ok(*first),
         _value => Err(RuntimeError {
             message: String::from("invalid expression"),
             full_text: String::from("cannot use first operation from anything but a tuple"),
-            location: first.location,
+            location: first.location.clone(),
+            note: None,
         }),
     }
 }
 
 fn eval_second<I: Printer>(
-    second: Second,
+    second: &Second,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
 ) -> Result<Value, RuntimeError> {
-    match eval(second.value, context, cache, io)? {
+    match eval_term(&second.value, env, context, cache, io)? {
         Value::Tuple(Tuple { first: _, second }) => Ok(*second),
         _value => Err(RuntimeError {
             message: String::from("invalid expression"),
             full_text: String::from("cannot use second operation from anything but a tuple"),
-            location: second.location,
+            location: second.location.clone(),
+            note: None,
         }),
     }
 }
 
-pub struct IO;
+fn eval_print<I: Printer>(
+    print_: &Print,
+    env: &Env,
+    context: &mut Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    let value = eval_term(&print_.value, env, context, cache, io)?;
 
-pub trait Printer {
-    fn print(&mut self, value: Value) -> Value;
+    Ok(io.print(value))
 }
-impl Printer for IO {
-    fn print(&mut self, value: Value) -> Value {
-        println!("{}", &value);
 
-        value
+fn eval_function(function: &Rc<Function>, env: &Env) -> Result<Value, RuntimeError> {
+    Ok(Value::Closure(Closure {
+        id: next_closure_id(),
+        function: function.clone(),
+        env: env.clone(),
+    }))
+}
+
+/// Evaluates a term up to the call in its tail position, if any, which runs
+/// at `depth`. Lets and ifs continue with their next term in the same frame.
+fn eval_tail<I: Printer>(
+    mut term: &Term,
+    env: &Env,
+    depth: usize,
+    context: &mut Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Tail, RuntimeError> {
+    loop {
+        let value = match term {
+            Term::Let(let_) => {
+                term = eval_let(let_, env, context, cache, io)?;
+                continue;
+            }
+            Term::If(if_) => {
+                term = eval_if(if_, env, context, cache, io)?;
+                continue;
+            }
+            Term::Call(call) => return eval_call(call, env, depth, context, cache, io),
+            Term::Int(int) => Value::Int(int.value),
+            Term::Str(str) => Value::Str(str.value.clone()),
+            Term::Bool(bool) => Value::Bool(bool.value),
+            Term::Function(function) => eval_function(function, env)?,
+            Term::Binary(binary) => eval_binary(binary, env, context, cache, io)?,
+            Term::Var(var) => eval_var(var, env, context)?,
+            Term::Tuple(tuple) => eval_tuple(tuple, env, context, cache, io)?,
+            Term::First(first) => eval_first(first, env, context, cache, io)?,
+            Term::Second(second) => eval_second(second, env, context, cache, io)?,
+            Term::Print(print) => eval_print(print, env, context, cache, io)?,
+        };
+
+        return Ok(Tail::Value(value));
     }
 }
 
-fn eval_print<I: Printer>(
-    print_: Print,
+/// Evaluates a term, running calls in tail position in a loop so tail
+/// recursive functions run in constant native stack. Every call made here
+/// runs one level deeper than the frame of the term, tail calls included.
+fn eval_term<I: Printer>(
+    term: &Term,
+    env: &Env,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
 ) -> Result<Value, RuntimeError> {
-    let value = eval(print_.value, context, cache, io)?;
+    // Calls are not the only terms nesting on the native stack, so its use
+    // is checked as well as the number of calls.
+    let max_depth = env.options().max_depth;
+    if env.stack_used() > max_depth.saturating_mul(STACK_PER_CALL) {
+        return Err(RuntimeError {
+            message: String::from("stack overflow"),
+            full_text: format!(
+                "terms nested deeper than the stack reserved for the maximum depth of {max_depth}"
+            ),
+            location: term.location().clone(),
+            note: None,
+        });
+    }
 
-    Ok(io.print(value))
+    // Keys of the pure calls whose result is the final value.
+    let mut memo = Vec::new();
+    let depth = env.depth() + 1;
+    let mut tail = eval_tail(term, env, depth, context, cache, io)?;
+
+    loop {
+        match tail {
+            Tail::Value(value) => {
+                for key in memo {
+                    cache.insert(key, value.clone());
+                }
+
+                return Ok(value);
+            }
+            Tail::Call(closure, env, key) => {
+                let function = &closure.function;
+
+                if let Some(cached_value) = key.as_ref().and_then(|key| cache.get(key)) {
+                    if let Some(profiler) = env.profiler() {
+                        profiler.hit(function.id, &function.location);
+                    }
+
+                    tail = Tail::Value(cached_value);
+                    continue;
+                }
+
+                memo.extend(key);
+
+                if let Some(profiler) = env.profiler() {
+                    profiler.enter(function.id, &function.location);
+                }
+
+                // Errors stop the program, leaving the profiler to unwind.
+                tail = eval_tail(&function.value, &env, depth, context, cache, io)?;
+
+                if let Some(profiler) = env.profiler() {
+                    profiler.exit();
+                }
+            }
+        }
+    }
 }
 
-fn eval_function(function: Function, context: &mut Context) -> Result<Value, RuntimeError> {
-    let context = Rc::new(RefCell::new(context.clone()));
+/// Calls allowed to run nested by [`eval`].
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Native stack to reserve per nested call, so a thread running [`eval`]
+/// can reach its maximum depth before overflowing its own stack. Terms
+/// nested so deeply that they use more than this per level of the maximum
+/// depth fail with a stack overflow error too, even with fewer calls.
+pub const STACK_PER_CALL: usize = 16 * 1024;
+
+/// Settings of an evaluation, shared by all of its frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Calls nested deeper fail with a stack overflow error.
+    pub max_depth: usize,
+    pub overflow: Overflow,
+}
 
-    Ok(Value::Closure(Closure {
-        parameters: function.parameters,
-        body: function.value.clone(),
-        context,
-    }))
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            overflow: Overflow::default(),
+        }
+    }
 }
 
+/// Resolves, analyzes and evaluates a program. Variables the program doesn't bind
+/// itself are looked up by name in `context`.
 pub fn eval<I: Printer>(
     term: Box<Term>,
     context: &mut Context,
     cache: &mut Cache,
     io: &mut I,
 ) -> Result<Value, RuntimeError> {
-    match *term {
-        Term::Let(let_) => eval_let(let_, context, cache, io),
-        Term::Int(int) => Ok(Value::Int(int.value)),
-        Term::Str(str) => Ok(Value::Str(str.value)),
-        Term::Bool(bool) => Ok(Value::Bool(bool.value)),
-        Term::Function(function) => eval_function(function, context),
-        Term::Call(call) => eval_call(call, context, cache, io),
-        Term::If(if_) => eval_if(if_, context, cache, io),
-        Term::Binary(binary) => eval_binary(binary, context, cache, io),
-        Term::Var(var) => eval_var(var, context),
-        Term::Tuple(tuple) => eval_tuple(tuple, context, cache, io),
-        Term::First(first) => eval_first(first, context, cache, io),
-        Term::Second(second) => eval_second(second, context, cache, io),
-        Term::Print(print) => eval_print(print, context, cache, io),
+    eval_with_options(term, Options::default(), context, cache, io)
+}
+
+/// Like [`eval`], with the given options. The calling thread needs about
+/// [`STACK_PER_CALL`] bytes of stack per level of `options.max_depth`.
+pub fn eval_with_options<I: Printer>(
+    mut term: Box<Term>,
+    options: Options,
+    context: &mut Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    resolver::resolve(&mut term);
+    effects::analyze(&mut term, context);
+
+    eval_term(&term, &Frame::root(options, None), context, cache, io)
+}
+
+/// Like [`eval_with_options`], measuring the calls of every function with
+/// `profiler`, where the program itself counts as one call of `<main>`.
+pub fn eval_profiled<I: Printer>(
+    mut term: Box<Term>,
+    options: Options,
+    profiler: &Profiler,
+    context: &mut Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    resolver::resolve(&mut term);
+    effects::analyze(&mut term, context);
+
+    let root = Frame::root(options, Some(profiler.clone()));
+    profiler.enter(profiler::MAIN, term.location());
+    let result = eval_term(&term, &root, context, cache, io);
+    profiler.unwind();
+
+    result
+}
+
+/// An interpreter keeping its globals, memoized results and output across
+/// evaluations, for programs embedding rinha. Values defined or registered
+/// on it are globals, visible to every program it evaluates, starting with
+/// the [`builtins`].
+pub struct Interpreter<P: Printer = Output<Stdout>> {
+    options: Options,
+    context: Context,
+    cache: Cache,
+    output: P,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter printing to stdout.
+    pub fn new() -> Self {
+        Self::with_output(Output::stdout())
+    }
+}
+
+impl<P: Printer> Interpreter<P> {
+    pub fn with_output(output: P) -> Self {
+        Self {
+            options: Options::default(),
+            context: builtins::context(),
+            cache: Cache::new(),
+            output,
+        }
+    }
+
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Makes `read_line` and `eof` read from `input` instead of stdin.
+    pub fn with_input<I: Input + 'static>(mut self, input: I) -> Self {
+        for native in builtins::input(input) {
+            let name = native.name().to_string();
+            self.define(&name, native);
+        }
+        self
+    }
+
+    /// Binds a global, replacing any previous value of `name`.
+    pub fn define<V: Into<Value>>(&mut self, name: &str, value: V) {
+        self.context.insert(name.to_string(), value.into());
+    }
+
+    /// Binds a Rust closure as a global function named `name`. Its
+    /// arguments are converted with [`crate::native::FromValue`], and
+    /// mismatched ones fail the call.
+    pub fn register<Args, F: NativeFn<Args> + 'static>(&mut self, name: &str, function: F) {
+        self.define(name, Native::new(name, function));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.context.get(name)
+    }
+
+    /// Evaluates a program, see [`eval_with_options`]. Printed values may
+    /// be buffered until [`Interpreter::flush`].
+    pub fn eval(&mut self, term: Term) -> Result<Value, RuntimeError> {
+        eval_with_options(
+            Box::new(term),
+            self.options,
+            &mut self.context,
+            &mut self.cache,
+            &mut self.output,
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    pub fn output(&self) -> &P {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut P {
+        &mut self.output
+    }
+
+    pub fn into_output(self) -> P {
+        self.output
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::HashSet, rc::Rc};
+
     use crate::ast::{Location, Term, Tuple, Var};
 
-    use super::{eval, Cache, Context, Printer, Value};
+    use crate::native::NativeError;
+
+    use super::{
+        eval, eval_with_options, Cache, Capture, Context, Interpreter, Options, Overflow, Printer,
+        Value,
+    };
 
     #[derive(Default)]
     struct DummyIO(String);
@@ -386,10 +755,7 @@ mod tests {
     }
 
     fn var(str: &str) -> Var {
-        Var {
-            text: str.to_string(),
-            location: location(),
-        }
+        Var::new(str, location())
     }
 
     fn let_(name: &str, value: Term, next: Term) -> Term {
@@ -426,8 +792,24 @@ mod tests {
     }
 
     fn var_(text: &str) -> Term {
-        Term::Var(Var {
-            text: text.to_string(),
+        Term::Var(var(text))
+    }
+
+    fn function(parameters: &[&str], value: Term) -> Term {
+        Term::Function(Rc::new(crate::ast::Function {
+            parameters: parameters.iter().map(|parameter| var(parameter)).collect(),
+            value: Box::new(value),
+            location: location(),
+            free: Vec::new(),
+            pure: false,
+            id: 0,
+        }))
+    }
+
+    fn call(callee: Term, arguments: Vec<Term>) -> Term {
+        Term::Call(crate::ast::Call {
+            callee: Box::new(callee),
+            arguments,
             location: location(),
         })
     }
@@ -481,4 +863,404 @@ mod tests {
         assert!(eq(result, v_int(3)));
         assert_eq!(io.0, "1\n2\n3\n");
     }
+
+    #[test]
+    fn closure_keeps_its_defining_scope() {
+        let mut io = DummyIO::default();
+
+        let program = let_(
+            "x",
+            int(1),
+            let_(
+                "f",
+                function(&[], var_("x")),
+                let_("x", int(2), add(call(var_("f"), vec![]), var_("x"))),
+            ),
+        );
+        let mut context = Context::new();
+        let mut cache = Cache::new();
+        let result = eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert!(eq(result, v_int(3)));
+    }
+
+    #[test]
+    fn closures_share_their_function() {
+        let (result, _) = run("let f = fn (x) => { fn () => { x } }; (f(1), f(2))");
+
+        let Value::Tuple(tuple) = result.unwrap() else {
+            panic!("expected a tuple");
+        };
+        let (Value::Closure(first), Value::Closure(second)) = (*tuple.first, *tuple.second) else {
+            panic!("expected closures");
+        };
+        assert!(Rc::ptr_eq(&first.function, &second.function));
+    }
+
+    #[test]
+    fn unbound_variables_fall_back_to_context() {
+        let mut io = DummyIO::default();
+
+        let program = add(var_("global"), int(1));
+        let mut context = Context::from([(String::from("global"), v_int(41))]);
+        let mut cache = Cache::new();
+        let result = eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert!(eq(result, v_int(42)));
+    }
+
+    #[test]
+    fn memoization_distinguishes_curried_closures() {
+        let mut io = DummyIO::default();
+
+        // let add = fn (a) => { fn (b) => { a + b } };
+        // print(add(1)(1)); print(add(2)(1))
+        let program = let_(
+            "add",
+            function(&["a"], function(&["b"], add(var_("a"), var_("b")))),
+            let_(
+                "_",
+                print_(call(call(var_("add"), vec![int(1)]), vec![int(1)])),
+                print_(call(call(var_("add"), vec![int(2)]), vec![int(1)])),
+            ),
+        );
+        let mut context = Context::new();
+        let mut cache = Cache::new();
+        eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert_eq!(io.0, "2\n3\n");
+    }
+
+    #[test]
+    fn memoization_reads_globals_at_call_time() {
+        let mut io = DummyIO::default();
+
+        // fn (x) => { x + k }, called once with each value of the global k
+        let program = let_(
+            "f",
+            function(&["x"], add(var_("x"), var_("k"))),
+            call(var_("f"), vec![int(1)]),
+        );
+        let mut cache = Cache::new();
+
+        let mut context = Context::from([(String::from("k"), v_int(10))]);
+        let first = eval(Box::new(program.clone()), &mut context, &mut cache, &mut io).unwrap();
+
+        let mut context = Context::from([(String::from("k"), v_int(20))]);
+        let second = eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert!(eq(first, v_int(11)));
+        assert!(eq(second, v_int(21)));
+    }
+
+    #[test]
+    fn functions_printing_in_nested_terms_are_not_memoized() {
+        let mut io = DummyIO::default();
+
+        // let f = fn (n) => { let _ = print(n); n }; let _ = f(1); f(1)
+        let program = let_(
+            "f",
+            function(&["n"], let_("_", print_(var_("n")), var_("n"))),
+            let_(
+                "_",
+                call(var_("f"), vec![int(1)]),
+                call(var_("f"), vec![int(1)]),
+            ),
+        );
+        let mut context = Context::new();
+        let mut cache = Cache::new();
+        eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert_eq!(io.0, "1\n1\n");
+    }
+
+    // Closures hold their environment behind a `RefCell`, but only their id
+    // is hashed.
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn values_hash_structurally() {
+        let values = HashSet::from([
+            v_tuple(Value::Str(String::from("1")), v_int(2)),
+            v_tuple(v_int(1), v_int(2)),
+            v_tuple(v_int(1), v_int(2)),
+            Value::Str(String::from("true")),
+            Value::Bool(true),
+        ]);
+
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn memoizes_calls_with_closures_in_tuples() {
+        let mut io = DummyIO::default();
+
+        // let f = fn (p) => { p }; let _ = print(f((1, f))); print(f((2, f)))
+        let program = let_(
+            "f",
+            function(&["p"], var_("p")),
+            let_(
+                "_",
+                print_(call(var_("f"), vec![tuple(int(1), var_("f"))])),
+                print_(call(var_("f"), vec![tuple(int(2), var_("f"))])),
+            ),
+        );
+        let mut context = Context::new();
+        let mut cache = Cache::new();
+        eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert_eq!(io.0, "(1, [closure])\n(2, [closure])\n");
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_stack() {
+        let mut io = DummyIO::default();
+
+        let source = "
+            let sum = fn (n, acc) => {
+                if (n == 0) { acc } else { let m = n - 1; sum(m, acc + n) }
+            };
+            let count = fn (n) => { if (n == 0) { print(n) } else { count(n - 1) } };
+            let _ = print(sum(100000, 0));
+            count(100000)
+        ";
+        let program = crate::parser::parse(source, "tests").unwrap().expression;
+        let mut context = Context::new();
+        let mut cache = Cache::new();
+        eval(Box::new(program), &mut context, &mut cache, &mut io).unwrap();
+
+        assert_eq!(io.0, "5000050000\n0\n");
+    }
+
+    #[test]
+    fn deeply_nested_recursion_is_a_runtime_error() {
+        // Every call nests the next one in many terms, using more native
+        // stack per call than reserved.
+        let mut nested = String::from("f(n - 1)");
+        for _ in 0..50 {
+            nested = format!("first((second((0, 1 + first(({nested}, 0)))), 0))");
+        }
+        let source = format!(
+            "let f = fn (n) => {{ if (n == 0) {{ 0 }} else {{ let a = {nested}; a }} }}; f(99)"
+        );
+        let options = Options {
+            max_depth: 100,
+            ..Options::default()
+        };
+
+        // Like the CLI, the thread has the stack of the maximum depth and
+        // room for everything else, like parsing the nested terms.
+        let error = std::thread::Builder::new()
+            .stack_size(options.max_depth * super::STACK_PER_CALL + 64 * 1024 * 1024)
+            .spawn(move || {
+                let program = crate::parser::parse(&source, "tests").unwrap().expression;
+                let mut io = DummyIO::default();
+                eval_with_options(
+                    Box::new(program),
+                    options,
+                    &mut Context::new(),
+                    &mut Cache::new(),
+                    &mut io,
+                )
+                .unwrap_err()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(error.message, "stack overflow");
+        assert_eq!(
+            error.full_text,
+            "terms nested deeper than the stack reserved for the maximum depth of 100"
+        );
+    }
+
+    #[test]
+    fn deep_recursion_is_a_runtime_error() {
+        let mut io = DummyIO::default();
+
+        let source = "let f = fn (n) => { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1000)";
+        let program = crate::parser::parse(source, "tests").unwrap().expression;
+        let mut context = Context::new();
+        let mut cache = Cache::new();
+        let options = Options {
+            max_depth: 100,
+            ..Options::default()
+        };
+        let error = eval_with_options(
+            Box::new(program),
+            options,
+            &mut context,
+            &mut cache,
+            &mut io,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.message, "stack overflow");
+        assert_eq!(
+            &source[error.location.start..error.location.end],
+            "f(n - 1)"
+        );
+    }
+
+    fn run(source: &str) -> (Result<Value, super::RuntimeError>, String) {
+        let mut io = DummyIO::default();
+        let program = crate::parser::parse(source, "tests").unwrap().expression;
+        let result = eval(
+            Box::new(program),
+            &mut Context::new(),
+            &mut Cache::new(),
+            &mut io,
+        );
+
+        (result, io.0)
+    }
+
+    #[test]
+    fn extra_arguments_are_an_arity_error() {
+        let source = "let f = fn (n) => { n }; f(1, print(2))";
+        let (result, output) = run(source);
+        let error = result.unwrap_err();
+        let note = error.note.unwrap();
+
+        assert_eq!(error.message, "arity mismatch");
+        assert_eq!(
+            error.full_text,
+            "function expects 1 arguments but 2 were given"
+        );
+        assert_eq!(
+            &source[error.location.start..error.location.end],
+            "f(1, print(2))"
+        );
+        assert_eq!(
+            &source[note.location.start..note.location.end],
+            "fn (n) => { n }"
+        );
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn missing_arguments_are_an_arity_error() {
+        let (result, _) = run("let n = 1; let f = fn (n) => { n }; f()");
+
+        assert_eq!(result.unwrap_err().message, "arity mismatch");
+    }
+
+    #[test]
+    fn partial_application_is_an_arity_error() {
+        let (result, _) = run("let add = fn (a, b) => { a + b }; let inc = add(1); inc(2)");
+
+        assert_eq!(result.unwrap_err().message, "arity mismatch");
+    }
+
+    #[test]
+    fn and_or_short_circuit() {
+        let (result, output) = run("let _ = false && print(1); true || print(2)");
+        assert!(eq(result.unwrap(), Value::Bool(true)));
+        assert_eq!(output, "");
+
+        let (result, output) = run("print(true) && print(false) || print(true)");
+        assert!(eq(result.unwrap(), Value::Bool(true)));
+        assert_eq!(output, "true\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn short_circuit_stops_recursion() {
+        let (result, output) =
+            run("let f = fn (n) => { n == 0 || f(print(n) - 1) }; let _ = f(2); f(0)");
+
+        assert!(eq(result.unwrap(), Value::Bool(true)));
+        assert_eq!(output, "2\n1\n");
+    }
+
+    #[test]
+    fn short_circuit_checks_left_hand_side() {
+        let (result, output) = run("1 && print(2)");
+
+        assert_eq!(result.unwrap_err().message, "invalid AND operation");
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let source =
+            "let fact = fn (n) => { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(25)";
+        let program = crate::parser::parse(source, "tests").unwrap().expression;
+
+        let (result, _) = run(source);
+        let error = result.unwrap_err();
+        assert_eq!(error.message, "integer overflow");
+        assert_eq!(&source[error.location.start..error.location.end], "n");
+
+        let options = Options {
+            overflow: Overflow::Promote,
+            ..Options::default()
+        };
+        let result = eval_with_options(
+            Box::new(program),
+            options,
+            &mut Context::new(),
+            &mut Cache::new(),
+            &mut DummyIO::default(),
+        );
+        assert_eq!(result.unwrap().to_string(), "15511210043330985984000000");
+    }
+
+    fn parse(source: &str) -> Term {
+        crate::parser::parse(source, "tests").unwrap().expression
+    }
+
+    #[test]
+    fn interpreter_keeps_globals_and_output() {
+        let mut interpreter = Interpreter::with_output(Capture::new());
+        interpreter.define("base", 40);
+        interpreter.define("pair", (1, "a"));
+
+        let value = interpreter
+            .eval(parse("print(base + first(pair))"))
+            .unwrap();
+        assert_eq!(value, Value::from(41));
+
+        interpreter.eval(parse("print(second(pair))")).unwrap();
+        assert_eq!(interpreter.output().text(), "41\na\n");
+    }
+
+    #[test]
+    fn calls_native_functions() {
+        let mut interpreter = Interpreter::with_output(Capture::new());
+        interpreter.register("add", |a: i64, b: i64| a + b);
+        interpreter.register("repeat", |text: String, times: i64| {
+            text.repeat(times.max(0) as usize)
+        });
+
+        let source = "let twice = fn (f, x) => { f(x, x) }; (twice(add, 21), repeat(\"ab\", 2))";
+        let value = interpreter.eval(parse(source)).unwrap();
+
+        assert_eq!(value, Value::from((42, "abab")));
+        assert_eq!(interpreter.get("add").unwrap().to_string(), "[closure]");
+    }
+
+    #[test]
+    fn native_function_errors() {
+        let mut interpreter = Interpreter::with_output(Capture::new());
+        interpreter.register("add", |a: i64, b: i64| a + b);
+        interpreter.register("fail", || -> Result<i64, NativeError> {
+            Err(NativeError::new("failed", "on purpose"))
+        });
+
+        let source = "add(1, print(true))";
+        let error = interpreter.eval(parse(source)).unwrap_err();
+        assert_eq!(error.message, "type mismatch");
+        assert_eq!(error.full_text, "argument 2: expected int, found bool");
+        assert_eq!(&source[error.location.start..error.location.end], source);
+
+        let error = interpreter.eval(parse("add(print(1))")).unwrap_err();
+        assert_eq!(error.message, "arity mismatch");
+        assert!(error.note.is_none());
+
+        let error = interpreter.eval(parse("1 + fail()")).unwrap_err();
+        assert_eq!(error.full_text, "on purpose");
+
+        // The arguments of a call with the wrong arity are never evaluated.
+        assert_eq!(interpreter.output().text(), "true\n");
+    }
 }
@@ -0,0 +1,409 @@
+use crate::{ast::Location, parser::ParseError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    /// The digits of an integer literal, checked to fit in an `i64` by the
+    /// parser, which knows whether it is negative.
+    Int(u64),
+    Str(String),
+    Ident(String),
+    Let,
+    Fn,
+    If,
+    Else,
+    Print,
+    First,
+    Second,
+    True,
+    False,
+    LParen,    // (
+    RParen,    // )
+    LBrace,    // {
+    RBrace,    // }
+    Comma,     // ,
+    Semicolon, // ;
+    Assign,    // =
+    Arrow,     // =>
+    Plus,      // +
+    Minus,     // -
+    Star,      // *
+    Slash,     // /
+    Percent,   // %
+    EqEq,      // ==
+    Neq,       // !=
+    Lt,        // <
+    Gt,        // >
+    Lte,       // <=
+    Gte,       // >=
+    And,       // &&
+    Or,        // ||
+    Eof,
+}
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Int(int) => return write!(f, "{int}"),
+            Self::Str(str) => return write!(f, "\"{str}\""),
+            Self::Ident(ident) => return write!(f, "{ident}"),
+            Self::Let => "let",
+            Self::Fn => "fn",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::Print => "print",
+            Self::First => "first",
+            Self::Second => "second",
+            Self::True => "true",
+            Self::False => "false",
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::LBrace => "{",
+            Self::RBrace => "}",
+            Self::Comma => ",",
+            Self::Semicolon => ";",
+            Self::Assign => "=",
+            Self::Arrow => "=>",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Star => "*",
+            Self::Slash => "/",
+            Self::Percent => "%",
+            Self::EqEq => "==",
+            Self::Neq => "!=",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Lte => "<=",
+            Self::Gte => ">=",
+            Self::And => "&&",
+            Self::Or => "||",
+            Self::Eof => "end of file",
+        };
+
+        f.write_str(text)
+    }
+}
+
+/// A token with the byte offsets it spans in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    filename: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str, filename: &'a str) -> Self {
+        Self {
+            source,
+            filename,
+            position: 0,
+        }
+    }
+
+    /// Consumes the whole source, always ending the token list with [`TokenKind::Eof`].
+    pub fn tokenize(mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = self.next_token()?;
+            let is_eof = token.kind == TokenKind::Eof;
+            tokens.push(token);
+
+            if is_eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        let mut chars = self.source[self.position..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let char = self.peek()?;
+        self.position += char.len_utf8();
+
+        Some(char)
+    }
+
+    fn error(&self, message: &str, full_text: String, start: usize) -> ParseError {
+        ParseError {
+            message: message.to_string(),
+            full_text,
+            location: Location::new(start, self.position, self.filename),
+        }
+    }
+
+    fn skip_trivia(&mut self) -> Result<(), ParseError> {
+        loop {
+            match (self.peek(), self.peek_next()) {
+                (Some(char), _) if char.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.bump();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.position;
+                    self.bump();
+                    self.bump();
+
+                    loop {
+                        match (self.bump(), self.peek()) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                break;
+                            }
+                            (Some(_), _) => {}
+                            (None, _) => {
+                                return Err(self.error(
+                                    "unterminated comment",
+                                    String::from("block comment is never closed with */"),
+                                    start,
+                                ))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, ParseError> {
+        self.skip_trivia()?;
+
+        let start = self.position;
+        let char = match self.bump() {
+            Some(char) => char,
+            None => {
+                return Ok(Token {
+                    kind: TokenKind::Eof,
+                    start,
+                    end: start,
+                })
+            }
+        };
+
+        let kind = match char {
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '{' => TokenKind::LBrace,
+            '}' => TokenKind::RBrace,
+            ',' => TokenKind::Comma,
+            ';' => TokenKind::Semicolon,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
+            '=' => match self.peek() {
+                Some('=') => {
+                    self.bump();
+                    TokenKind::EqEq
+                }
+                Some('>') => {
+                    self.bump();
+                    TokenKind::Arrow
+                }
+                _ => TokenKind::Assign,
+            },
+            '!' if self.peek() == Some('=') => {
+                self.bump();
+                TokenKind::Neq
+            }
+            '<' if self.peek() == Some('=') => {
+                self.bump();
+                TokenKind::Lte
+            }
+            '<' => TokenKind::Lt,
+            '>' if self.peek() == Some('=') => {
+                self.bump();
+                TokenKind::Gte
+            }
+            '>' => TokenKind::Gt,
+            '&' if self.peek() == Some('&') => {
+                self.bump();
+                TokenKind::And
+            }
+            '|' if self.peek() == Some('|') => {
+                self.bump();
+                TokenKind::Or
+            }
+            '"' => self.string(start)?,
+            char if char.is_ascii_digit() => self.int(start)?,
+            char if char.is_alphabetic() || char == '_' => self.ident(start),
+            char => {
+                return Err(self.error(
+                    "unexpected character",
+                    format!("{char:?} is not valid in a rinha program"),
+                    start,
+                ))
+            }
+        };
+
+        Ok(Token {
+            kind,
+            start,
+            end: self.position,
+        })
+    }
+
+    fn string(&mut self, start: usize) -> Result<TokenKind, ParseError> {
+        let mut value = String::new();
+
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(TokenKind::Str(value)),
+                Some('\\') => match self.bump() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('0') => value.push('\0'),
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some(char) => {
+                        return Err(self.error(
+                            "invalid escape sequence",
+                            format!("\\{char} is not a known escape sequence"),
+                            self.position - char.len_utf8() - 1,
+                        ))
+                    }
+                    None => break,
+                },
+                Some(char) => value.push(char),
+                None => break,
+            }
+        }
+
+        Err(self.error(
+            "unterminated string",
+            String::from("string literal is never closed with \""),
+            start,
+        ))
+    }
+
+    fn int(&mut self, start: usize) -> Result<TokenKind, ParseError> {
+        while matches!(self.peek(), Some(char) if char.is_ascii_digit()) {
+            self.bump();
+        }
+
+        let text = &self.source[start..self.position];
+        text.parse::<u64>().map(TokenKind::Int).map_err(|_| {
+            self.error(
+                "integer literal too large",
+                format!("{text} does not fit in a 64-bit signed integer"),
+                start,
+            )
+        })
+    }
+
+    fn ident(&mut self, start: usize) -> TokenKind {
+        while matches!(self.peek(), Some(char) if char.is_alphanumeric() || char == '_') {
+            self.bump();
+        }
+
+        match &self.source[start..self.position] {
+            "let" => TokenKind::Let,
+            "fn" => TokenKind::Fn,
+            "if" => TokenKind::If,
+            "else" => TokenKind::Else,
+            "print" => TokenKind::Print,
+            "first" => TokenKind::First,
+            "second" => TokenKind::Second,
+            "true" => TokenKind::True,
+            "false" => TokenKind::False,
+            ident => TokenKind::Ident(ident.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lexer, TokenKind};
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        Lexer::new(source, "tests")
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            kinds("= => == != < <= > >= && ||"),
+            vec![
+                TokenKind::Assign,
+                TokenKind::Arrow,
+                TokenKind::EqEq,
+                TokenKind::Neq,
+                TokenKind::Lt,
+                TokenKind::Lte,
+                TokenKind::Gt,
+                TokenKind::Gte,
+                TokenKind::And,
+                TokenKind::Or,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(
+            kinds("let fib_2 = fn"),
+            vec![
+                TokenKind::Let,
+                TokenKind::Ident(String::from("fib_2")),
+                TokenKind::Assign,
+                TokenKind::Fn,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_comments() {
+        assert_eq!(
+            kinds("1 // one\n/* two\n */ 2"),
+            vec![TokenKind::Int(1), TokenKind::Int(2), TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(
+            kinds(r#""a\n\"b\"""#),
+            vec![TokenKind::Str(String::from("a\n\"b\"")), TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn token_offsets() {
+        let tokens = Lexer::new("print (\"hi\")", "tests").tokenize().unwrap();
+
+        assert_eq!((tokens[2].start, tokens[2].end), (7, 11));
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert!(Lexer::new("\"abc", "tests").tokenize().is_err());
+    }
+}
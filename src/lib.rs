@@ -1,3 +1,22 @@
 pub mod ast;
 pub mod binary;
+pub mod builtins;
+pub mod bytecode;
+pub mod cache;
+pub mod diagnostic;
+pub mod effects;
+pub mod environment;
+pub mod formatter;
+pub mod input;
 pub mod interpreter;
+pub mod lexer;
+pub mod native;
+pub mod optimizer;
+pub mod output;
+pub mod parser;
+pub mod profiler;
+pub mod repl;
+pub mod resolver;
+pub mod testing;
+pub mod typechecker;
+pub mod vm;
@@ -1,36 +1,733 @@
-use clap::Parser;
+use std::{
+    io::{BufRead, IsTerminal, Read, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use lipsum::{
-    ast::File,
-    interpreter::{eval, Cache, Context, IO},
+    ast::{File, Term},
+    binary::Overflow,
+    builtins, bytecode,
+    cache::{self, Cache},
+    diagnostic::{self, Diagnostic},
+    formatter,
+    interpreter::{
+        self, eval_profiled, eval_with_options, Context, Options, Output, Printer, RuntimeError,
+        Value,
+    },
+    optimizer, parser,
+    profiler::Profiler,
+    repl::{self, Repl},
+    testing::{self, Expected},
+    typechecker, vm,
 };
 
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum Backend {
+    /// Walk the AST directly.
+    #[default]
+    Tree,
+    /// Compile to bytecode and run it on a stack VM.
+    Vm,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum Integers {
+    /// Fail when a result doesn't fit in 64 bits.
+    #[default]
+    Checked,
+    /// Continue with arbitrary precision when a result doesn't fit in 64 bits.
+    Big,
+}
+
+impl Backend {
+    /// Evaluates a program, looking up its unbound variables in `context`.
+    fn eval<P: Printer>(
+        self,
+        term: Term,
+        options: Options,
+        profiler: Option<&Profiler>,
+        context: &mut Context,
+        cache: &mut Cache,
+        io: &mut P,
+    ) -> Result<Value, RuntimeError> {
+        let term = Box::new(term);
+
+        match (self, profiler) {
+            (Backend::Tree, None) => eval_with_options(term, options, context, cache, io),
+            (Backend::Tree, Some(profiler)) => {
+                eval_profiled(term, options, profiler, context, cache, io)
+            }
+            (Backend::Vm, None) => vm::run_with_options(
+                &bytecode::compile(&term, context),
+                options,
+                context,
+                cache,
+                io,
+            ),
+            (Backend::Vm, Some(profiler)) => {
+                let program = bytecode::compile(&term, context);
+                vm::run_profiled(&program, options, profiler, context, cache, io)
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ProfileFormat {
+    /// A table of calls, cache hits and time per function.
+    Table,
+    /// The exclusive time of every call stack in nanoseconds, in the folded
+    /// format flame graph tools read.
+    Folded,
+}
+
+static DEFAULT_PATH: &str = "/var/rinha/source.rinha.json";
+
+/// Path read from stdin.
+static STDIN_PATH: &str = "-";
+
+/// Stack for everything but nested calls, like parsing deeply nested terms.
+const MAIN_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Largest stack a thread is started with, whatever the maximum depth.
+const MAX_STACK_SIZE: usize = 1024 * 1024 * 1024;
+
+#[derive(Args, Debug)]
+struct Source {
+    /// Path to a `.rinha` source or a `.json` AST, `-` reads stdin.
+    #[arg(default_value = DEFAULT_PATH)]
+    path: String,
+
+    /// Read the input as a JSON AST, the default for `.json` paths.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct Evaluation {
+    /// Maximum number of memoized call results kept, 0 disables memoization.
+    #[arg(long, default_value_t = cache::DEFAULT_CAPACITY)]
+    cache_size: usize,
+
+    /// Disable memoization of pure function calls.
+    #[arg(long, conflicts_with = "cache_size")]
+    no_cache: bool,
+
+    /// Print memoization hits, misses and evictions to stderr at exit.
+    #[arg(long)]
+    cache_stats: bool,
+
+    /// Deepest nesting of calls allowed before failing with a stack overflow.
+    #[arg(long, default_value_t = interpreter::DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+
+    /// How integer arithmetic handles overflow.
+    #[arg(long, value_enum, default_value_t)]
+    integers: Integers,
+
+    /// Write printed values to a file instead of stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+impl Default for Evaluation {
+    fn default() -> Self {
+        Self {
+            cache_size: cache::DEFAULT_CAPACITY,
+            no_cache: false,
+            cache_stats: false,
+            max_depth: interpreter::DEFAULT_MAX_DEPTH,
+            integers: Integers::default(),
+            output: None,
+        }
+    }
+}
+
+impl Evaluation {
+    fn cache(&self) -> Cache {
+        match self.no_cache {
+            true => Cache::with_capacity(0),
+            false => Cache::with_capacity(self.cache_size),
+        }
+    }
+
+    fn options(&self) -> Options {
+        Options {
+            max_depth: self.max_depth,
+            overflow: match self.integers {
+                Integers::Checked => Overflow::Error,
+                Integers::Big => Overflow::Promote,
+            },
+        }
+    }
+
+    fn output(&self) -> Result<Output<Box<dyn Write>>, Failure> {
+        let writer: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(
+                std::fs::File::create(path)
+                    .map_err(|err| Failure::Io(format!("failed to create {path}: {err}\n")))?,
+            ),
+            None => Box::new(std::io::stdout()),
+        };
+
+        Ok(Output::new(writer))
+    }
+
+    fn report_cache(&self, cache: &Cache) {
+        if self.cache_stats {
+            eprintln!("cache: {} ({} entries)", cache.stats(), cache.len());
+        }
+    }
+
+    /// Stack the evaluation runs on. The tree walker recurses on the native
+    /// stack, so it needs to be big enough to reach the maximum depth, while
+    /// the VM keeps its frames on the heap.
+    fn stack_size(&self, backend: Backend) -> Result<usize, Failure> {
+        let size = match backend {
+            Backend::Tree => self
+                .max_depth
+                .saturating_mul(interpreter::STACK_PER_CALL)
+                .saturating_add(MAIN_STACK_SIZE),
+            Backend::Vm => MAIN_STACK_SIZE,
+        };
+
+        match size <= MAX_STACK_SIZE {
+            true => Ok(size),
+            false => Err(Failure::Usage(format!(
+                "--max-depth {} needs more than {} MiB of stack with the tree backend, \
+                 try --backend vm\n",
+                self.max_depth,
+                MAX_STACK_SIZE / 1024 / 1024
+            ))),
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
+struct Profiling {
+    /// Measure calls, cache hits and time per function, reported at exit.
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "table"
+    )]
+    profile: Option<ProfileFormat>,
+
+    /// Write the profile to a file instead of stderr.
+    #[arg(long, requires = "profile")]
+    profile_output: Option<String>,
+}
+
+impl Profiling {
+    fn profiler(&self, term: &Term) -> Option<Profiler> {
+        self.profile.map(|_| Profiler::new(term))
+    }
+
+    /// Writes the profile, with locations as lines and columns of `source`.
+    fn report(&self, profiler: &Profiler, source: Option<&str>) -> Result<(), Failure> {
+        let report = match self.profile {
+            Some(ProfileFormat::Folded) => profiler.folded(source),
+            Some(ProfileFormat::Table) | None => profiler.table(source),
+        };
+
+        match &self.profile_output {
+            Some(path) => std::fs::write(path, report)
+                .map_err(|err| Failure::Io(format!("failed to write {path}: {err}\n"))),
+            None => {
+                eprint!("{report}");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a program, the default when no command is given.
+    Run {
+        #[command(flatten)]
+        source: Source,
+
+        /// Evaluator used to run the program.
+        #[arg(long, value_enum, default_value_t)]
+        backend: Backend,
+
+        /// Fold constants, dead branches and unused lets before running.
+        #[arg(long)]
+        optimize: bool,
+
+        #[command(flatten)]
+        profiling: Profiling,
+
+        #[command(flatten)]
+        evaluation: Evaluation,
+
+        /// Arguments given to the program as `argc` and `arg(i)`, after `--`.
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Type-check a program and report every error without running it.
+    Check {
+        #[command(flatten)]
+        source: Source,
+    },
+    /// Print a program as canonical rinha source.
+    Fmt {
+        #[command(flatten)]
+        source: Source,
+
+        /// Fail when the source isn't formatted instead of printing it.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Print the syntax tree of a program as JSON, in the schema `.json`
+    /// inputs are read in.
+    Ast {
+        #[command(flatten)]
+        source: Source,
+
+        /// Print the tree after folding constants, dead branches and unused lets.
+        #[arg(long)]
+        optimize: bool,
+    },
+    /// Read expressions from stdin line by line, printing their values.
+    /// `let name = value` without a next term defines a global.
+    Repl {
+        #[command(flatten)]
+        evaluation: Evaluation,
+    },
+    /// Run every `.rinha` and `.json` program in a directory, comparing what
+    /// it prints and its exit status with its `.expected` file. The status is
+    /// 0 unless the file ends with an `exit: <status>` line.
+    Test {
+        /// Directory holding the programs and their `.expected` files.
+        #[arg(default_value = "examples")]
+        dir: String,
+
+        /// Evaluator used to run the programs.
+        #[arg(long, value_enum, default_value_t)]
+        backend: Backend,
+    },
+}
+
+impl Command {
+    /// Runs the program at `path` with the default options.
+    fn run(path: String) -> Self {
+        Self::Run {
+            source: Source { path, json: false },
+            backend: Backend::default(),
+            optimize: false,
+            profiling: Profiling::default(),
+            evaluation: Evaluation::default(),
+            args: Vec::new(),
+        }
+    }
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::run(DEFAULT_PATH.to_string())
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
-struct Command {
-    #[arg(short, long)]
+#[command(
+    after_help = "Exit codes: 0 on success, 1 when the program fails while running or \
+`fmt --check` finds it unformatted or `test` finds failing programs, \
+2 on invalid arguments, like `fmt --check` on a JSON AST, 3 when the program doesn't parse or type-check and 4 when a file \
+can't be read or written."
+)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Program to run, like `run <path>`, as taken before the subcommands.
+    #[arg(short, long, hide = true)]
     file: Option<String>,
 }
 
-static DEFAULT_PATH: &'static str = "/var/rinha/source.rinha.json";
+impl Cli {
+    fn command(self) -> Command {
+        match (self.command, self.file) {
+            (Some(command), _) => command,
+            (None, Some(path)) => Command::run(path),
+            (None, None) => Command::default(),
+        }
+    }
+}
 
-fn main() -> Result<(), String> {
-    let command = Command::parse();
-    let path = match command.file {
-        Some(path) => path,
-        None => DEFAULT_PATH.to_string(),
-    };
+/// Why a command failed, with the report to print. Each reason exits with
+/// its own code, listed in the help.
+enum Failure {
+    /// The program failed while running.
+    Runtime(String),
+    /// The source isn't formatted.
+    Unformatted(String),
+    /// Programs of `test` didn't behave as expected.
+    Failed(String),
+    /// The program doesn't parse or type-check.
+    Invalid(String),
+    /// A file couldn't be read or written.
+    Io(String),
+    /// The arguments don't make sense together, like clap's own errors.
+    Usage(String),
+}
+
+impl Failure {
+    fn code(&self) -> u8 {
+        match self {
+            Self::Runtime(_) | Self::Unformatted(_) | Self::Failed(_) => 1,
+            Self::Usage(_) => 2,
+            Self::Invalid(_) => 3,
+            Self::Io(_) => 4,
+        }
+    }
+
+    fn report(&self) -> &str {
+        match self {
+            Self::Runtime(report)
+            | Self::Unformatted(report)
+            | Self::Failed(report)
+            | Self::Invalid(report)
+            | Self::Io(report)
+            | Self::Usage(report) => report,
+        }
+    }
+}
+
+/// The program being run, keeping the text it was read from so diagnostics
+/// can point back into it.
+struct Program {
+    /// Name locations refer to the program by.
+    name: String,
+    text: String,
+    json: bool,
+}
+
+impl Program {
+    fn read(source: &Source) -> Result<Self, Failure> {
+        let json = source.json || source.path.ends_with(".json");
+
+        if source.path == STDIN_PATH {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|err| Failure::Io(format!("failed to read stdin: {err}\n")))?;
+
+            return Ok(Self {
+                name: String::from("<stdin>"),
+                text,
+                json,
+            });
+        }
+
+        let path = &source.path;
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Failure::Io(format!("failed to read {path}: {err}\n")))?;
+
+        Ok(Self {
+            name: path.clone(),
+            text,
+            json,
+        })
+    }
+
+    /// Loads the program as a JSON AST or parses it as rinha source.
+    fn load(&self) -> Result<File, Failure> {
+        match self.json {
+            true => serde_json::from_str(&self.text)
+                .map_err(|err| Failure::Invalid(format!("invalid AST at {}: {err}\n", self.name))),
+            false => parser::parse(&self.text, &self.name)
+                .map_err(|err| Failure::Invalid(self.report(&err))),
+        }
+    }
+
+    /// The rinha source locations in `filename` point into. JSON ASTs only
+    /// carry the name of their source file, so it is read from disk when
+    /// present.
+    fn source(&self, filename: &str) -> Option<String> {
+        match self.json {
+            true => std::fs::read_to_string(filename).ok(),
+            false => Some(self.text.clone()),
+        }
+    }
+
+    /// Renders a diagnostic against the rinha source.
+    fn report<D: Diagnostic>(&self, diagnostic: &D) -> String {
+        let source = self.source(&diagnostic.location().filename);
+
+        diagnostic::render(diagnostic, source.as_deref())
+    }
+}
 
-    let file = std::fs::read_to_string(&path).expect(&format!("failed to read file at {}", &path));
+fn run(
+    source: &Source,
+    backend: Backend,
+    optimize: bool,
+    profiling: &Profiling,
+    evaluation: &Evaluation,
+    args: &[String],
+) -> Result<(), Failure> {
+    let program = Program::read(source)?;
+    let mut parsed_file = program.load()?;
 
-    let parsed_file: File = serde_json::from_str(&file).unwrap();
+    if optimize {
+        optimizer::optimize(&mut parsed_file.expression);
+    }
 
-    let entrypoint = Box::new(parsed_file.expression);
+    let mut cache = evaluation.cache();
+    let mut io = evaluation.output()?;
+    let mut context = builtins::context();
+    context.extend(builtins::arguments(args));
+    let profiler = profiling.profiler(&parsed_file.expression);
 
-    let mut context = Context::new();
-    let mut cache = Cache::new();
-    let mut io = IO {};
-    let _ = eval(entrypoint, &mut context, &mut cache, &mut io).unwrap();
+    let result = backend.eval(
+        parsed_file.expression,
+        evaluation.options(),
+        profiler.as_ref(),
+        &mut context,
+        &mut cache,
+        &mut io,
+    );
+
+    // Values printed before an error are written out before it is reported.
+    let flushed = io.flush();
+    evaluation.report_cache(&cache);
+    if let Some(profiler) = &profiler {
+        let source = program.source(&parsed_file.location.filename);
+        profiling.report(profiler, source.as_deref())?;
+    }
+    result.map_err(|err| Failure::Runtime(program.report(&err)))?;
+
+    flushed.map_err(|err| Failure::Io(format!("failed to write the output: {err}\n")))
+}
+
+fn check(source: &Source) -> Result<(), Failure> {
+    let program = Program::read(source)?;
+    let parsed_file = program.load()?;
+
+    typechecker::check(&parsed_file.expression)
+        .map(|_| ())
+        .map_err(|errors| Failure::Invalid(errors.iter().map(|err| program.report(err)).collect()))
+}
+
+fn fmt(source: &Source, check: bool) -> Result<(), Failure> {
+    let program = Program::read(source)?;
+    if check && program.json {
+        return Err(Failure::Usage(format!(
+            "{} is a JSON AST, only rinha sources can be checked\n",
+            program.name
+        )));
+    }
+
+    let parsed_file = program.load()?;
+    let formatted = formatter::format(&parsed_file.expression);
+
+    if !check {
+        print!("{formatted}");
+        return Ok(());
+    }
+
+    if program.text == formatted {
+        return Ok(());
+    }
+
+    Err(Failure::Unformatted(format!(
+        "{} is not formatted, starting at line {}\n",
+        program.name,
+        first_difference(&program.text, &formatted)
+    )))
+}
+
+/// The first line, counting from 1, where two different texts differ.
+fn first_difference(text: &str, formatted: &str) -> usize {
+    let mut text = text.split('\n');
+    let mut formatted = formatted.split('\n');
+    let mut line = 1;
+
+    while text.next() == formatted.next() {
+        line += 1;
+    }
+
+    line
+}
+
+fn ast(source: &Source, optimize: bool) -> Result<(), Failure> {
+    let program = Program::read(source)?;
+    let mut parsed_file = program.load()?;
+
+    if optimize {
+        optimizer::optimize(&mut parsed_file.expression);
+    }
+
+    let json = serde_json::to_string_pretty(&parsed_file)
+        .map_err(|err| Failure::Invalid(format!("failed to serialize the AST: {err}\n")))?;
+    println!("{json}");
+
+    Ok(())
+}
+
+/// Evaluates stdin with the tree walker, one input at a time. Inputs left
+/// open, like an unclosed block, continue on the next line until a blank one.
+fn repl(evaluation: &Evaluation) -> Result<(), Failure> {
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut session = Repl::new(evaluation.options(), evaluation.cache());
+    let mut io = evaluation.output()?;
+    let mut input = String::new();
+
+    loop {
+        if interactive {
+            print!("{}", if input.is_empty() { "> " } else { "... " });
+            let _ = std::io::stdout().flush();
+        }
+
+        let mut line = String::new();
+        let read = stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|err| Failure::Io(format!("failed to read stdin: {err}\n")))?;
+
+        let blank = line.trim().is_empty();
+        if read > 0 && blank && input.is_empty() {
+            continue;
+        }
+
+        input.push_str(&line);
+        if read > 0 && !blank && repl::is_incomplete(&input) {
+            continue;
+        }
+
+        if !input.trim().is_empty() {
+            let result = session.eval(&input, &mut io);
+            io.flush()
+                .map_err(|err| Failure::Io(format!("failed to write the output: {err}\n")))?;
+
+            match result {
+                Ok(Some(value)) => println!("{value}"),
+                Ok(None) => {}
+                Err(report) => eprint!("{report}"),
+            }
+        }
+        input.clear();
+
+        if read == 0 {
+            break;
+        }
+    }
+
+    evaluation.report_cache(session.cache());
 
     Ok(())
 }
+
+/// Runs a program of `test` with the default options.
+fn test_program(
+    term: Term,
+    backend: Backend,
+    io: &mut impl Printer,
+) -> Result<Value, RuntimeError> {
+    backend.eval(
+        term,
+        Options::default(),
+        None,
+        &mut testing::context(),
+        &mut Cache::new(),
+        io,
+    )
+}
+
+fn test(dir: &str, backend: Backend) -> Result<(), Failure> {
+    let programs = testing::discover(Path::new(dir))
+        .map_err(|err| Failure::Io(format!("failed to read {dir}: {err}\n")))?;
+    let mut failed = 0;
+
+    for (path, expected) in &programs {
+        let result = match std::fs::read_to_string(expected) {
+            Ok(text) => testing::run(path, |term, io| test_program(term, backend, io))
+                .map_err(|err| format!("failed to read it: {err}\n"))
+                .and_then(|outcome| testing::compare(&Expected::parse(&text), &outcome)),
+            Err(err) => Err(format!("failed to read {}: {err}\n", expected.display())),
+        };
+
+        match result {
+            Ok(()) => println!("ok {}", path.display()),
+            Err(report) => {
+                failed += 1;
+                println!("FAILED {}\n{report}", path.display());
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", programs.len() - failed);
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(Failure::Failed(format!(
+            "{failed} of {} programs failed\n",
+            programs.len()
+        ))),
+    }
+}
+
+fn main() -> ExitCode {
+    let command = Cli::parse().command();
+
+    let stack_size = match &command {
+        Command::Run {
+            backend,
+            evaluation,
+            ..
+        } => evaluation.stack_size(*backend),
+        Command::Repl { evaluation } => evaluation.stack_size(Backend::Tree),
+        Command::Test { backend, .. } => Evaluation::default().stack_size(*backend),
+        Command::Check { .. } | Command::Fmt { .. } | Command::Ast { .. } => Ok(MAIN_STACK_SIZE),
+    };
+
+    let result = stack_size
+        .and_then(|stack_size| {
+            std::thread::Builder::new()
+                .stack_size(stack_size)
+                .spawn(move || match &command {
+                    Command::Run {
+                        source,
+                        backend,
+                        optimize,
+                        profiling,
+                        evaluation,
+                        args,
+                    } => run(source, *backend, *optimize, profiling, evaluation, args),
+                    Command::Check { source } => check(source),
+                    Command::Fmt { source, check } => fmt(source, *check),
+                    Command::Ast { source, optimize } => ast(source, *optimize),
+                    Command::Repl { evaluation } => repl(evaluation),
+                    Command::Test { dir, backend } => test(dir, *backend),
+                })
+                .map_err(|err| {
+                    Failure::Usage(format!(
+                        "failed to start the interpreter with {} MiB of stack: {err}\n",
+                        stack_size / 1024 / 1024
+                    ))
+                })
+        })
+        .and_then(|thread| {
+            thread
+                .join()
+                .unwrap_or_else(|err| std::panic::resume_unwind(err))
+        });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprint!("{}", failure.report());
+            ExitCode::from(failure.code())
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use num_bigint::BigInt;
+
+use crate::{
+    ast::Location,
+    interpreter::{next_closure_id, RuntimeError, Tuple, Value},
+};
+
+/// An error raised by a native function, reported at the call that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeError {
+    pub message: String,
+    pub full_text: String,
+}
+
+impl NativeError {
+    pub fn new(message: &str, full_text: &str) -> Self {
+        Self {
+            message: message.to_string(),
+            full_text: full_text.to_string(),
+        }
+    }
+
+    /// Error for a value of the wrong type, `expected` being the name of the
+    /// type wanted, like `int`.
+    pub fn mismatch(expected: &str, value: &Value) -> Self {
+        Self::new(
+            "type mismatch",
+            &format!("expected {expected}, found {}", value.kind()),
+        )
+    }
+
+    pub(crate) fn at(self, location: &Location) -> RuntimeError {
+        RuntimeError {
+            message: self.message,
+            full_text: self.full_text,
+            location: location.clone(),
+            note: None,
+        }
+    }
+}
+
+/// Conversion of the arguments of a native function from rinha values.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, NativeError>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, NativeError> {
+        Ok(value)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, NativeError> {
+        match value {
+            Value::Int(int) => Ok(int),
+            Value::BigInt(int) => Err(NativeError::new(
+                "integer overflow",
+                &format!("{int} does not fit in a 64-bit integer"),
+            )),
+            value => Err(NativeError::mismatch("int", &value)),
+        }
+    }
+}
+
+impl FromValue for BigInt {
+    fn from_value(value: Value) -> Result<Self, NativeError> {
+        match value {
+            Value::Int(int) => Ok(BigInt::from(int)),
+            Value::BigInt(int) => Ok(int.as_ref().clone()),
+            value => Err(NativeError::mismatch("int", &value)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, NativeError> {
+        match value {
+            Value::Bool(bool) => Ok(bool),
+            value => Err(NativeError::mismatch("bool", &value)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, NativeError> {
+        match value {
+            Value::Str(str) => Ok(str),
+            value => Err(NativeError::mismatch("str", &value)),
+        }
+    }
+}
+
+impl<A: FromValue, B: FromValue> FromValue for (A, B) {
+    fn from_value(value: Value) -> Result<Self, NativeError> {
+        match value {
+            Value::Tuple(tuple) => {
+                Ok((A::from_value(*tuple.first)?, B::from_value(*tuple.second)?))
+            }
+            value => Err(NativeError::mismatch("tuple", &value)),
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(int: i64) -> Self {
+        Value::Int(int)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(bool: bool) -> Self {
+        Value::Bool(bool)
+    }
+}
+
+impl From<String> for Value {
+    fn from(str: String) -> Self {
+        Value::Str(str)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(str: &str) -> Self {
+        Value::Str(str.to_string())
+    }
+}
+
+impl<A: Into<Value>, B: Into<Value>> From<(A, B)> for Value {
+    fn from((first, second): (A, B)) -> Self {
+        Value::Tuple(Tuple {
+            first: Box::new(first.into()),
+            second: Box::new(second.into()),
+        })
+    }
+}
+
+impl From<Native> for Value {
+    fn from(native: Native) -> Self {
+        Value::Native(Rc::new(native))
+    }
+}
+
+/// What a native function may return: a value, or a result failing the call.
+pub trait IntoNativeResult {
+    fn into_native_result(self) -> Result<Value, NativeError>;
+}
+
+impl<T: Into<Value>> IntoNativeResult for T {
+    fn into_native_result(self) -> Result<Value, NativeError> {
+        Ok(self.into())
+    }
+}
+
+impl<T: Into<Value>> IntoNativeResult for Result<T, NativeError> {
+    fn into_native_result(self) -> Result<Value, NativeError> {
+        self.map(Into::into)
+    }
+}
+
+/// Rust closures callable from rinha, taking up to four arguments that
+/// implement [`FromValue`]. `Args` only tells the implementations apart.
+pub trait NativeFn<Args> {
+    const ARITY: usize;
+
+    /// Calls the closure with exactly [`Self::ARITY`] arguments.
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, NativeError>;
+}
+
+macro_rules! native_fn {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> NativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoNativeResult,
+            $($arg: FromValue,)*
+        {
+            const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, arguments: Vec<Value>) -> Result<Value, NativeError> {
+                let mut arguments = arguments.into_iter().enumerate();
+
+                $(
+                    let (index, value) = arguments.next().expect("native function arity");
+                    let $arg = $arg::from_value(value).map_err(|err| NativeError {
+                        full_text: format!("argument {}: {}", index + 1, err.full_text),
+                        ..err
+                    })?;
+                )*
+
+                (self)($($arg),*).into_native_result()
+            }
+        }
+    };
+}
+
+native_fn!();
+native_fn!(A);
+native_fn!(A, B);
+native_fn!(A, B, C);
+native_fn!(A, B, C, D);
+
+/// A function implemented in Rust. Like closures, a native function is only
+/// equal to itself.
+pub struct Native {
+    name: String,
+    arity: usize,
+    id: usize,
+    interactive: bool,
+    pure: bool,
+    function: Box<dyn Fn(Vec<Value>) -> Result<Value, NativeError>>,
+}
+
+impl Native {
+    pub fn new<Args, F: NativeFn<Args> + 'static>(name: &str, function: F) -> Self {
+        Self {
+            name: name.to_string(),
+            arity: F::ARITY,
+            id: next_closure_id(),
+            interactive: false,
+            pure: false,
+            function: Box::new(move |arguments| function.call(arguments)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Marks a function waiting on the user, like `read_line`. Printed
+    /// values are shown before every call, so prompts are seen in time.
+    pub fn interactive(mut self) -> Self {
+        self.interactive = true;
+        self
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Marks a function without effects, whose result only depends on its
+    /// arguments, so rinha functions calling it can still be memoized.
+    pub fn pure(mut self) -> Self {
+        self.pure = true;
+        self
+    }
+
+    pub fn is_pure(&self) -> bool {
+        self.pure
+    }
+
+    /// Calls the function with exactly [`Native::arity`] arguments.
+    pub fn call(&self, arguments: Vec<Value>) -> Result<Value, NativeError> {
+        debug_assert_eq!(arguments.len(), self.arity);
+
+        (self.function)(arguments)
+    }
+}
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Native")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Native {}
+
+impl Hash for Native {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use crate::interpreter::Value;
+
+    use super::{FromValue, Native, NativeError};
+
+    #[test]
+    fn converts_values() {
+        assert_eq!(i64::from_value(Value::from(3)), Ok(3));
+        assert_eq!(String::from_value(Value::from("a")), Ok(String::from("a")));
+        assert_eq!(
+            <(i64, bool)>::from_value(Value::from((1, true))),
+            Ok((1, true))
+        );
+        assert_eq!(
+            BigInt::from_value(Value::from(BigInt::from(2).pow(70))),
+            Ok(BigInt::from(2).pow(70))
+        );
+
+        let error = i64::from_value(Value::from("a")).unwrap_err();
+        assert_eq!(error.full_text, "expected int, found str");
+
+        let error = i64::from_value(Value::from(BigInt::from(2).pow(70))).unwrap_err();
+        assert_eq!(error.message, "integer overflow");
+    }
+
+    #[test]
+    fn calls_rust_closures() {
+        let add = Native::new("add", |a: i64, b: i64| a + b);
+        assert_eq!(add.arity(), 2);
+        assert_eq!(
+            add.call(vec![Value::from(1), Value::from(2)]),
+            Ok(Value::from(3))
+        );
+
+        let constant = Native::new("answer", || 42);
+        assert_eq!(constant.arity(), 0);
+        assert_eq!(constant.call(Vec::new()), Ok(Value::from(42)));
+
+        let failing = Native::new("fail", |_: Value| -> Result<i64, _> {
+            Err(NativeError::new("failed", "on purpose"))
+        });
+        assert_eq!(
+            failing.call(vec![Value::from(1)]).unwrap_err().message,
+            "failed"
+        );
+    }
+
+    #[test]
+    fn reports_the_argument_of_the_wrong_type() {
+        let add = Native::new("add", |a: i64, b: i64| a + b);
+        let error = add
+            .call(vec![Value::from(1), Value::from(true)])
+            .unwrap_err();
+
+        assert_eq!(error.message, "type mismatch");
+        assert_eq!(error.full_text, "argument 2: expected int, found bool");
+    }
+
+    #[test]
+    fn natives_are_only_equal_to_themselves() {
+        let a = Value::from(Native::new("id", |value: Value| value));
+        let b = Value::from(Native::new("id", |value: Value| value));
+
+        assert_eq!(a, a.clone());
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,300 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Binary, Bool, Element, Int, Location, Str, Term},
+    binary::Overflow,
+    interpreter::Value,
+};
+
+/// Simplifies a program without changing what it prints or returns:
+///
+/// - binary operations on literals are folded with the interpreter's own
+///   operations, unless they would fail, which is left for runtime;
+/// - `if`s on a literal condition are replaced by the branch taken;
+/// - `first` and `second` of a tuple literal are replaced by the element,
+///   when the other one can be dropped;
+/// - `let`s whose name is never used are removed, when their value can be
+///   dropped.
+///
+/// A term can only be dropped when evaluating it can neither print nor fail,
+/// so prints always happen in the same order.
+pub fn optimize(term: &mut Term) {
+    let placeholder = Term::Bool(Bool::default());
+    let taken = std::mem::replace(term, placeholder);
+
+    *term = Optimizer::default().term(taken);
+}
+
+/// The value of a literal.
+fn constant(term: &Term) -> Option<Value> {
+    match term {
+        Term::Int(int) => Some(Value::Int(int.value)),
+        Term::Str(str) => Some(Value::Str(str.value.clone())),
+        Term::Bool(bool) => Some(Value::Bool(bool.value)),
+        _ => None,
+    }
+}
+
+/// The literal of a value, for values that can be written as one.
+fn literal(value: Value, location: Location) -> Option<Term> {
+    match value {
+        Value::Int(value) => Some(Term::Int(Int { value, location })),
+        Value::Str(value) => Some(Term::Str(Str { value, location })),
+        Value::Bool(value) => Some(Term::Bool(Bool { value, location })),
+        _ => None,
+    }
+}
+
+/// Whether `name` appears anywhere in `term`, shadowed or not.
+fn uses(term: &Term, name: &str) -> bool {
+    match term {
+        Term::Int(_) | Term::Str(_) | Term::Bool(_) => false,
+        Term::Var(var) => var.text == name,
+        Term::Call(call) => {
+            uses(&call.callee, name) || call.arguments.iter().any(|term| uses(term, name))
+        }
+        Term::Binary(binary) => uses(&binary.lhs, name) || uses(&binary.rhs, name),
+        Term::Function(function) => uses(&function.value, name),
+        Term::Let(let_) => uses(&let_.value, name) || uses(&let_.next, name),
+        Term::If(if_) => {
+            uses(&if_.condition, name) || uses(&if_.then, name) || uses(&if_.otherwise, name)
+        }
+        Term::Print(print) => uses(&print.value, name),
+        Term::First(first) => uses(&first.value, name),
+        Term::Second(second) => uses(&second.value, name),
+        Term::Tuple(tuple) => uses(&tuple.first, name) || uses(&tuple.second, name),
+    }
+}
+
+/// Folds a binary operation whose operands were already optimized.
+fn fold(binary: Binary) -> Term {
+    let Some(lhs) = constant(&binary.lhs) else {
+        return Term::Binary(binary);
+    };
+
+    // The right hand side is never evaluated when the left one decides.
+    if let Ok(Some(value)) = lhs.short_circuit(&binary.op, binary.lhs.location()) {
+        if let Some(term) = literal(value, binary.location.clone()) {
+            return term;
+        }
+    }
+
+    let Some(rhs) = constant(&binary.rhs) else {
+        return Term::Binary(binary);
+    };
+
+    // Overflows are left for runtime too, which may promote the result.
+    match lhs.binary_op(&binary, &rhs, Overflow::Error) {
+        Ok(value) => literal(value, binary.location.clone()).unwrap_or(Term::Binary(binary)),
+        Err(_) => Term::Binary(binary),
+    }
+}
+
+#[derive(Default)]
+struct Optimizer {
+    /// Names bound by the enclosing terms.
+    scope: Vec<String>,
+}
+
+impl Optimizer {
+    /// Whether evaluating `term` can neither print nor fail.
+    fn droppable(&self, term: &Term) -> bool {
+        match term {
+            Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Function(_) => true,
+            Term::Var(var) => self.scope.contains(&var.text),
+            Term::Tuple(tuple) => self.droppable(&tuple.first) && self.droppable(&tuple.second),
+            _ => false,
+        }
+    }
+
+    fn boxed(&mut self, term: &mut Box<Term>) {
+        let placeholder = Term::Bool(Bool::default());
+        let taken = std::mem::replace(term.as_mut(), placeholder);
+
+        **term = self.term(taken);
+    }
+
+    fn term(&mut self, term: Term) -> Term {
+        match term {
+            Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) => term,
+            Term::Call(mut call) => {
+                self.boxed(&mut call.callee);
+                call.arguments = call
+                    .arguments
+                    .into_iter()
+                    .map(|argument| self.term(argument))
+                    .collect();
+
+                Term::Call(call)
+            }
+            Term::Binary(mut binary) => {
+                self.boxed(&mut binary.lhs);
+                self.boxed(&mut binary.rhs);
+
+                fold(binary)
+            }
+            Term::Function(mut function) => {
+                let function_mut = Rc::make_mut(&mut function);
+                let depth = self.scope.len();
+                self.scope.extend(
+                    function_mut
+                        .parameters
+                        .iter()
+                        .map(|parameter| parameter.text.clone()),
+                );
+                self.boxed(&mut function_mut.value);
+                self.scope.truncate(depth);
+
+                Term::Function(function)
+            }
+            Term::Let(mut let_) => {
+                // Like the interpreters, a function bound by a `let` sees
+                // itself under that name.
+                let depth = self.scope.len();
+                if matches!(let_.value.as_ref(), Term::Function(_)) {
+                    self.scope.push(let_.name.text.clone());
+                }
+                self.boxed(&mut let_.value);
+                self.scope.truncate(depth);
+
+                self.scope.push(let_.name.text.clone());
+                self.boxed(&mut let_.next);
+                self.scope.truncate(depth);
+
+                match !uses(&let_.next, &let_.name.text) && self.droppable(&let_.value) {
+                    true => *let_.next,
+                    false => Term::Let(let_),
+                }
+            }
+            Term::If(mut if_) => {
+                self.boxed(&mut if_.condition);
+
+                match constant(&if_.condition) {
+                    Some(Value::Bool(true)) => self.term(*if_.then),
+                    Some(Value::Bool(false)) => self.term(*if_.otherwise),
+                    _ => {
+                        self.boxed(&mut if_.then);
+                        self.boxed(&mut if_.otherwise);
+
+                        Term::If(if_)
+                    }
+                }
+            }
+            Term::Print(mut print) => {
+                self.boxed(&mut print.value);
+
+                Term::Print(print)
+            }
+            Term::First(mut first) => {
+                self.boxed(&mut first.value);
+
+                match *first.value {
+                    Term::Tuple(tuple) if self.droppable(&tuple.second) => *tuple.first,
+                    value => {
+                        first.value = Box::new(value);
+                        Term::First(first)
+                    }
+                }
+            }
+            Term::Second(mut second) => {
+                self.boxed(&mut second.value);
+
+                match *second.value {
+                    Term::Tuple(tuple) if self.droppable(&tuple.first) => *tuple.second,
+                    value => {
+                        second.value = Box::new(value);
+                        Term::Second(second)
+                    }
+                }
+            }
+            Term::Tuple(mut tuple) => {
+                self.boxed(&mut tuple.first);
+                self.boxed(&mut tuple.second);
+
+                Term::Tuple(tuple)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{formatter::format, parser::parse};
+
+    use super::optimize;
+
+    fn optimized(source: &str) -> String {
+        let mut term = parse(source, "tests").unwrap().expression;
+        optimize(&mut term);
+
+        format(&term).trim_end().to_string()
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(optimized("1 + 2 * 3"), "7");
+        assert_eq!(optimized("\"a\" + 1 == \"a1\""), "true");
+        assert_eq!(optimized("x + (2 * 3)"), "x + 6");
+        assert_eq!(optimized("false && x"), "false");
+        assert_eq!(optimized("true || print(1)"), "true");
+        assert_eq!(optimized("true && x"), "true && x");
+    }
+
+    #[test]
+    fn leaves_failures_for_runtime() {
+        assert_eq!(optimized("1 / 0"), "1 / 0");
+        assert_eq!(
+            optimized("9223372036854775807 + 1"),
+            "9223372036854775807 + 1"
+        );
+        assert_eq!(optimized("1 + true"), "1 + true");
+        assert_eq!(optimized("1 && true"), "1 && true");
+    }
+
+    #[test]
+    fn removes_dead_branches() {
+        assert_eq!(
+            optimized("if (1 < 2) { print(1) } else { print(2) }"),
+            "print(1)"
+        );
+        assert_eq!(optimized("if (false) { 1 } else { 2 + 3 }"), "5");
+        assert_eq!(
+            optimized("if (x) { 1 } else { 2 }"),
+            "if (x) {\n  1\n} else {\n  2\n}"
+        );
+        assert_eq!(
+            optimized("if (1) { 1 } else { 2 }"),
+            "if (1) {\n  1\n} else {\n  2\n}"
+        );
+    }
+
+    #[test]
+    fn projects_tuple_literals() {
+        assert_eq!(optimized("first((1 + 1, \"b\"))"), "2");
+        assert_eq!(optimized("let a = 1; second((a, 2))"), "2");
+        assert_eq!(optimized("first((1, print(2)))"), "first((1, print(2)))");
+        assert_eq!(optimized("second((x, 2))"), "second((x, 2))");
+    }
+
+    #[test]
+    fn removes_unused_pure_lets() {
+        assert_eq!(optimized("let x = 1 + 1; let f = fn () => { 2 }; 3"), "3");
+        assert_eq!(optimized("let x = 1; let y = x; 3"), "3");
+        assert_eq!(optimized("let x = 1; x"), "let x = 1;\nx");
+        assert_eq!(optimized("let x = print(1); 3"), "let x = print(1);\n3");
+        assert_eq!(optimized("let x = 1 / 0; 3"), "let x = 1 / 0;\n3");
+        assert_eq!(optimized("let x = y; 3"), "let x = y;\n3");
+    }
+
+    #[test]
+    fn keeps_print_order() {
+        assert_eq!(
+            optimized("let a = print(1); let b = print(2); (b, a)"),
+            "let a = print(1);\nlet b = print(2);\n(b, a)"
+        );
+        assert_eq!(
+            optimized("let _ = print(1 + 1); if (true) { print(\"a\" + \"b\") } else { 0 }"),
+            "let _ = print(2);\nprint(\"ab\")"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Stdout, Write},
+    path::Path,
+};
+
+use crate::interpreter::Value;
+
+/// Where the values of `print` go.
+pub trait Printer {
+    /// Prints a value, returning it as the result of `print`.
+    fn print(&mut self, value: Value) -> Value;
+
+    /// Writes out anything buffered, reporting the first error met while
+    /// printing, if any.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writes out anything buffered before the program waits for input, so
+    /// prompts show up first. Errors are kept for [`Printer::flush`].
+    fn show(&mut self) {}
+}
+
+/// Writes printed values to a [`Write`], one per line, buffering them until
+/// flushed. Write errors don't stop the program, the first one is returned
+/// by [`Printer::flush`] instead.
+pub struct Output<W: Write> {
+    writer: BufWriter<W>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            error: None,
+        }
+    }
+}
+
+impl Output<Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl Output<File> {
+    /// Prints to the file at `path`, creating or truncating it.
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Printer for Output<W> {
+    fn print(&mut self, value: Value) -> Value {
+        if let Err(err) = writeln!(self.writer, "{value}") {
+            self.error.get_or_insert(err);
+        }
+
+        value
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        self.writer.flush()
+    }
+
+    fn show(&mut self) {
+        if let Err(err) = self.writer.flush() {
+            self.error.get_or_insert(err);
+        }
+    }
+}
+
+/// Keeps printed values in memory, one per line, for hosts that want the
+/// output of a program as a string.
+#[derive(Debug, Default)]
+pub struct Capture {
+    text: String,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns everything printed so far, leaving the capture empty.
+    pub fn take(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+}
+
+impl Printer for Capture {
+    fn print(&mut self, value: Value) -> Value {
+        self.text.push_str(&value.to_string());
+        self.text.push('\n');
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use crate::interpreter::Value;
+
+    use super::{Capture, Output, Printer};
+
+    /// A writer failing every write.
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn buffers_until_flushed() {
+        let mut buffer = Vec::new();
+        let mut output = Output::new(&mut buffer);
+
+        let value = output.print(Value::Int(1));
+        output.print(Value::Str(String::from("a")));
+        assert!(matches!(value, Value::Int(1)));
+
+        output.flush().unwrap();
+        drop(output);
+        assert_eq!(String::from_utf8(buffer).unwrap(), "1\na\n");
+    }
+
+    #[test]
+    fn reports_write_errors_on_flush() {
+        let mut output = Output::new(Closed);
+        output.print(Value::Int(1));
+
+        let error = output.flush().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn captures_to_a_string() {
+        let mut capture = Capture::new();
+        capture.print(Value::Bool(true));
+        capture.print(Value::Int(2));
+
+        assert_eq!(capture.text(), "true\n2\n");
+        assert_eq!(capture.take(), "true\n2\n");
+        assert!(capture.text().is_empty());
+    }
+}
@@ -0,0 +1,526 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        Binary, BinaryOp, Bool, Call, Element, File, First, Function, If, Int, Let, Location,
+        Print, Second, Str, Term, Tuple, Var,
+    },
+    lexer::{Lexer, Token, TokenKind},
+};
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub full_text: String,
+    pub location: Location,
+}
+
+/// Parses a `.rinha` source into a [`File`], using `filename` for every [`Location`].
+pub fn parse(source: &str, filename: &str) -> Result<File, ParseError> {
+    let tokens = Lexer::new(source, filename).tokenize()?;
+    let mut parser = Parser::new(tokens, filename);
+
+    let expression = parser.parse_term()?;
+    parser.expect(TokenKind::Eof)?;
+
+    Ok(File {
+        name: filename.to_string(),
+        expression,
+        location: Location::new(0, source.len(), filename),
+    })
+}
+
+/// A single input of the REPL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// A `let` without a next term, optionally ending with `;`, defining a
+    /// global for the inputs that follow.
+    Let(Var, Term),
+    Term(Term),
+}
+
+/// Parses a REPL input. Unlike [`parse`], a `let` may end the input, in which
+/// case it is returned as a definition instead of a term.
+pub fn parse_input(source: &str, filename: &str) -> Result<Input, ParseError> {
+    let tokens = Lexer::new(source, filename).tokenize()?;
+    let mut parser = Parser::new(tokens, filename);
+
+    if parser.peek().kind != TokenKind::Let {
+        let term = parser.parse_term()?;
+        parser.expect(TokenKind::Eof)?;
+
+        return Ok(Input::Term(term));
+    }
+
+    let (start, name, value) = parser.parse_binding()?;
+    if parser.peek().kind == TokenKind::Eof {
+        return Ok(Input::Let(name, value));
+    }
+
+    parser.expect(TokenKind::Semicolon)?;
+    if parser.peek().kind == TokenKind::Eof {
+        return Ok(Input::Let(name, value));
+    }
+
+    let term = parser.parse_let_next(start, name, value)?;
+    parser.expect(TokenKind::Eof)?;
+
+    Ok(Input::Term(term))
+}
+
+/// An integer literal, failing when it doesn't fit in 64 bits.
+fn int(value: i128, location: Location) -> Result<Term, ParseError> {
+    match i64::try_from(value) {
+        Ok(value) => Ok(Term::Int(Int { value, location })),
+        Err(_) => Err(ParseError {
+            message: String::from("integer literal too large"),
+            full_text: format!("{value} does not fit in a 64-bit signed integer"),
+            location,
+        }),
+    }
+}
+
+/// The binary operator a token stands for.
+fn operator(kind: &TokenKind) -> Option<BinaryOp> {
+    let operator = match kind {
+        TokenKind::Or => BinaryOp::Or,
+        TokenKind::And => BinaryOp::And,
+        TokenKind::EqEq => BinaryOp::Eq,
+        TokenKind::Neq => BinaryOp::Neq,
+        TokenKind::Lt => BinaryOp::Lt,
+        TokenKind::Gt => BinaryOp::Gt,
+        TokenKind::Lte => BinaryOp::Lte,
+        TokenKind::Gte => BinaryOp::Gte,
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Sub,
+        TokenKind::Star => BinaryOp::Mul,
+        TokenKind::Slash => BinaryOp::Div,
+        TokenKind::Percent => BinaryOp::Rem,
+        _ => return None,
+    };
+
+    Some(operator)
+}
+
+pub struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    filename: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token>, filename: &'a str) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            filename,
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+        if token.kind != TokenKind::Eof {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    fn location(&self, start: usize, end: usize) -> Location {
+        Location::new(start, end, self.filename)
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let token = self.peek();
+
+        ParseError {
+            message: String::from("unexpected token"),
+            full_text: format!("expected {expected}, found {}", token.kind),
+            location: self.location(token.start, token.end),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
+        if self.peek().kind == kind {
+            return Ok(self.bump());
+        }
+
+        Err(self.unexpected(&kind.to_string()))
+    }
+
+    pub fn parse_term(&mut self) -> Result<Term, ParseError> {
+        match self.peek().kind {
+            TokenKind::Let => self.parse_let(),
+            _ => self.parse_binary(0),
+        }
+    }
+
+    fn parse_let(&mut self) -> Result<Term, ParseError> {
+        let (start, name, value) = self.parse_binding()?;
+        self.expect(TokenKind::Semicolon)?;
+
+        self.parse_let_next(start, name, value)
+    }
+
+    /// Parses `let name = value`, returning where the `let` starts.
+    fn parse_binding(&mut self) -> Result<(usize, Var, Term), ParseError> {
+        let start = self.expect(TokenKind::Let)?.start;
+        let name = self.parse_var()?;
+        self.expect(TokenKind::Assign)?;
+        let value = self.parse_term()?;
+
+        Ok((start, name, value))
+    }
+
+    fn parse_let_next(&mut self, start: usize, name: Var, value: Term) -> Result<Term, ParseError> {
+        let next = self.parse_term()?;
+
+        Ok(Term::Let(Let {
+            location: self.location(start, next.location().end),
+            name,
+            value: Box::new(value),
+            next: Box::new(next),
+        }))
+    }
+
+    fn parse_var(&mut self) -> Result<Var, ParseError> {
+        match self.peek().kind.clone() {
+            TokenKind::Ident(text) => {
+                let token = self.bump();
+
+                Ok(Var::new(&text, self.location(token.start, token.end)))
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Term, ParseError> {
+        let mut lhs = self.parse_call()?;
+
+        while let Some(op) = operator(&self.peek().kind) {
+            let precedence = op.precedence();
+            if precedence <= min_precedence {
+                break;
+            }
+
+            self.bump();
+            let rhs = self.parse_binary(precedence)?;
+
+            lhs = Term::Binary(Binary {
+                location: self.location(lhs.location().start, rhs.location().end),
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+            });
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_call(&mut self) -> Result<Term, ParseError> {
+        let mut callee = self.parse_primary()?;
+
+        while self.peek().kind == TokenKind::LParen {
+            self.bump();
+            let arguments = self.parse_arguments()?;
+            let end = self.expect(TokenKind::RParen)?.end;
+
+            callee = Term::Call(Call {
+                location: self.location(callee.location().start, end),
+                callee: Box::new(callee),
+                arguments,
+            });
+        }
+
+        Ok(callee)
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Term>, ParseError> {
+        let mut arguments = Vec::new();
+
+        while self.peek().kind != TokenKind::RParen {
+            arguments.push(self.parse_term()?);
+
+            if self.peek().kind != TokenKind::Comma {
+                break;
+            }
+            self.bump();
+        }
+
+        Ok(arguments)
+    }
+
+    fn parse_block(&mut self) -> Result<(Term, usize), ParseError> {
+        self.expect(TokenKind::LBrace)?;
+        let term = self.parse_term()?;
+        let end = self.expect(TokenKind::RBrace)?.end;
+
+        Ok((term, end))
+    }
+
+    /// Parses `keyword(term)`, used by `print`, `first` and `second`.
+    fn parse_unary(&mut self) -> Result<(Box<Term>, Location), ParseError> {
+        let start = self.bump().start;
+        self.expect(TokenKind::LParen)?;
+        let value = self.parse_term()?;
+        let end = self.expect(TokenKind::RParen)?.end;
+
+        Ok((Box::new(value), self.location(start, end)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Term, ParseError> {
+        let token = self.peek().clone();
+        let location = self.location(token.start, token.end);
+
+        match token.kind {
+            TokenKind::Int(digits) => {
+                self.bump();
+                int(i128::from(digits), location)
+            }
+            TokenKind::Minus => {
+                self.bump();
+                match self.peek().kind {
+                    // The minus is part of the literal, so `i64::MIN` fits.
+                    TokenKind::Int(digits) => {
+                        let end = self.bump().end;
+                        int(-i128::from(digits), self.location(token.start, end))
+                    }
+                    _ => Err(self.unexpected("an integer")),
+                }
+            }
+            TokenKind::Str(value) => {
+                self.bump();
+                Ok(Term::Str(Str { value, location }))
+            }
+            TokenKind::True | TokenKind::False => {
+                self.bump();
+                Ok(Term::Bool(Bool {
+                    value: token.kind == TokenKind::True,
+                    location,
+                }))
+            }
+            TokenKind::Ident(_) => Ok(Term::Var(self.parse_var()?)),
+            TokenKind::LParen => {
+                self.bump();
+                let first = self.parse_term()?;
+
+                if self.peek().kind != TokenKind::Comma {
+                    self.expect(TokenKind::RParen)?;
+                    return Ok(first);
+                }
+
+                self.bump();
+                let second = self.parse_term()?;
+                let end = self.expect(TokenKind::RParen)?.end;
+
+                Ok(Term::Tuple(Tuple {
+                    first: Box::new(first),
+                    second: Box::new(second),
+                    location: self.location(token.start, end),
+                }))
+            }
+            TokenKind::LBrace => Ok(self.parse_block()?.0),
+            TokenKind::Fn => {
+                self.bump();
+                self.expect(TokenKind::LParen)?;
+
+                let mut parameters = Vec::new();
+                while self.peek().kind != TokenKind::RParen {
+                    parameters.push(self.parse_var()?);
+
+                    if self.peek().kind != TokenKind::Comma {
+                        break;
+                    }
+                    self.bump();
+                }
+
+                self.expect(TokenKind::RParen)?;
+                self.expect(TokenKind::Arrow)?;
+                let (value, end) = self.parse_block()?;
+
+                Ok(Term::Function(Rc::new(Function {
+                    parameters,
+                    value: Box::new(value),
+                    location: self.location(token.start, end),
+                    free: Vec::new(),
+                    pure: false,
+                    id: 0,
+                })))
+            }
+            TokenKind::If => {
+                self.bump();
+                self.expect(TokenKind::LParen)?;
+                let condition = self.parse_term()?;
+                self.expect(TokenKind::RParen)?;
+                let (then, _) = self.parse_block()?;
+                self.expect(TokenKind::Else)?;
+                let (otherwise, end) = self.parse_block()?;
+
+                Ok(Term::If(If {
+                    condition: Box::new(condition),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                    location: self.location(token.start, end),
+                }))
+            }
+            TokenKind::Print => {
+                let (value, location) = self.parse_unary()?;
+                Ok(Term::Print(Print { value, location }))
+            }
+            TokenKind::First => {
+                let (value, location) = self.parse_unary()?;
+                Ok(Term::First(First { value, location }))
+            }
+            TokenKind::Second => {
+                let (value, location) = self.parse_unary()?;
+                Ok(Term::Second(Second { value, location }))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{BinaryOp, Element, File, Term};
+
+    use super::{parse, parse_input, Input};
+
+    fn term(source: &str) -> Term {
+        parse(source, "tests").unwrap().expression
+    }
+
+    fn from_json(json: &str) -> File {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn matches_reference_ast() {
+        let examples = [
+            (
+                include_str!("../examples/sum.rinha"),
+                include_str!("../examples/sum.json"),
+                "files/sum.rinha",
+            ),
+            (
+                include_str!("../examples/combination.rinha"),
+                include_str!("../examples/combination.json"),
+                "files/combination.rinha",
+            ),
+            (
+                include_str!("../examples/hello-world.rinha"),
+                include_str!("../examples/hello-world.json"),
+                "print.rinha",
+            ),
+        ];
+
+        for (source, json, filename) in examples {
+            let parsed = parse(source, filename).unwrap();
+
+            assert_eq!(parsed.expression, from_json(json).expression);
+        }
+    }
+
+    #[test]
+    fn binary_precedence() {
+        let Term::Binary(binary) = term("1 + 2 * 3 == 7 || false") else {
+            panic!("expected a binary term");
+        };
+        assert_eq!(binary.op, BinaryOp::Or);
+
+        let Term::Binary(eq) = *binary.lhs else {
+            panic!("expected a binary term");
+        };
+        assert_eq!(eq.op, BinaryOp::Eq);
+
+        let Term::Binary(add) = *eq.lhs else {
+            panic!("expected a binary term");
+        };
+        assert_eq!(add.op, BinaryOp::Add);
+        assert!(matches!(*add.rhs, Term::Binary(ref mul) if mul.op == BinaryOp::Mul));
+    }
+
+    #[test]
+    fn left_associative() {
+        let Term::Binary(binary) = term("10 - 2 - 3") else {
+            panic!("expected a binary term");
+        };
+
+        assert!(matches!(*binary.rhs, Term::Int(ref int) if int.value == 3));
+        assert_eq!(binary.location().start, 0);
+        assert_eq!(binary.location().end, 10);
+    }
+
+    #[test]
+    fn tuples_and_grouping() {
+        assert!(matches!(term("(1, \"a\")"), Term::Tuple(_)));
+        assert!(matches!(term("(1 + 2)"), Term::Binary(_)));
+    }
+
+    #[test]
+    fn negative_int() {
+        assert!(matches!(term("-5"), Term::Int(ref int) if int.value == -5));
+        assert!(
+            matches!(term("-9223372036854775808"), Term::Int(ref int) if int.value == i64::MIN)
+        );
+
+        let error = parse("9223372036854775808", "tests").unwrap_err();
+        assert_eq!(error.message, "integer literal too large");
+        assert_eq!(
+            error.full_text,
+            "9223372036854775808 does not fit in a 64-bit signed integer"
+        );
+    }
+
+    #[test]
+    fn curried_call() {
+        let Term::Call(call) = term("f(1)(2, 3)") else {
+            panic!("expected a call term");
+        };
+
+        assert_eq!(call.arguments.len(), 2);
+        assert!(matches!(*call.callee, Term::Call(_)));
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let error = parse("let x = 1 x", "tests").unwrap_err();
+
+        assert_eq!(error.location.start, 10);
+        assert_eq!(error.full_text, "expected ;, found x");
+    }
+
+    #[test]
+    fn trailing_tokens() {
+        assert!(parse("1 2", "tests").is_err());
+    }
+
+    #[test]
+    fn input_definitions() {
+        for source in ["let x = 1 + 2", "let x = 1 + 2;"] {
+            let Input::Let(name, value) = parse_input(source, "tests").unwrap() else {
+                panic!("expected a definition");
+            };
+
+            assert_eq!(name.text, "x");
+            assert!(matches!(value, Term::Binary(_)));
+        }
+    }
+
+    #[test]
+    fn input_terms() {
+        assert!(matches!(
+            parse_input("let x = 1; x", "tests").unwrap(),
+            Input::Term(Term::Let(_))
+        ));
+        assert!(matches!(
+            parse_input("1 + 2", "tests").unwrap(),
+            Input::Term(Term::Binary(_))
+        ));
+        assert!(parse_input("let x = 1 x", "tests").is_err());
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Write,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::{Location, Term},
+    diagnostic::line_column,
+};
+
+/// Id the top-level program is profiled under, as if it were a function.
+pub(crate) const MAIN: usize = usize::MAX;
+
+/// What the profiler measured for one function literal, every closure of it
+/// counted together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// Name of the `let` the function is bound to, `<main>` for the program
+    /// itself and `<anonymous>` for the others.
+    pub name: String,
+    pub location: Location,
+    /// Calls, including the ones answered from the memoization cache.
+    pub calls: u64,
+    pub cache_hits: u64,
+    /// Time spent in the function and the calls it made. Recursive calls
+    /// are only counted once, from the outermost one.
+    pub inclusive: Duration,
+    /// Time spent in the function itself.
+    pub exclusive: Duration,
+}
+
+/// A call stack seen while profiling, the root one being empty.
+#[derive(Debug)]
+struct Node {
+    function: usize,
+    parent: usize,
+    children: HashMap<usize, usize>,
+    exclusive: Duration,
+}
+
+/// A call being profiled.
+#[derive(Debug)]
+struct Active {
+    function: usize,
+    node: usize,
+    start: Instant,
+    /// Time spent in the calls it made so far.
+    children: Duration,
+}
+
+#[derive(Debug)]
+struct Function {
+    profile: FunctionProfile,
+    /// Calls of the function running, to count recursive calls once.
+    running: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Names of the functions bound by a `let`, by their location.
+    names: HashMap<Location, String>,
+    /// Index in `functions` of the function ids seen.
+    ids: HashMap<usize, usize>,
+    functions: Vec<Function>,
+    nodes: Vec<Node>,
+    stack: Vec<Active>,
+}
+
+/// Collects call counts, cache hits and time per function while a program
+/// runs, see [`crate::interpreter::eval_profiled`]. Profilers are cheap
+/// handles: clones share what was measured.
+///
+/// Tail calls replace their caller, as they run in its place, so they are
+/// counted as calls of the caller's caller.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    state: Rc<RefCell<State>>,
+}
+
+/// Records the name of every function bound by a `let` in `term`.
+fn collect_names(term: &Term, names: &mut HashMap<Location, String>) {
+    match term {
+        Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) => {}
+        Term::Call(call) => {
+            collect_names(&call.callee, names);
+            for argument in &call.arguments {
+                collect_names(argument, names);
+            }
+        }
+        Term::Binary(binary) => {
+            collect_names(&binary.lhs, names);
+            collect_names(&binary.rhs, names);
+        }
+        Term::Function(function) => collect_names(&function.value, names),
+        Term::Let(let_) => {
+            if let Term::Function(function) = let_.value.as_ref() {
+                names.insert(function.location.clone(), let_.name.text.clone());
+            }
+
+            collect_names(&let_.value, names);
+            collect_names(&let_.next, names);
+        }
+        Term::If(if_) => {
+            collect_names(&if_.condition, names);
+            collect_names(&if_.then, names);
+            collect_names(&if_.otherwise, names);
+        }
+        Term::Print(print) => collect_names(&print.value, names),
+        Term::First(first) => collect_names(&first.value, names),
+        Term::Second(second) => collect_names(&second.value, names),
+        Term::Tuple(tuple) => {
+            collect_names(&tuple.first, names);
+            collect_names(&tuple.second, names);
+        }
+    }
+}
+
+/// `file:line:column` of a location, when the source it points into is known.
+fn position(location: &Location, source: Option<&str>) -> String {
+    match source {
+        Some(source) => {
+            let (line, column) = line_column(source, location.start);
+            format!("{}:{line}:{column}", location.filename)
+        }
+        None => format!("{}:{}", location.filename, location.start),
+    }
+}
+
+/// Formats a duration in milliseconds, with microsecond precision.
+fn millis(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl Profiler {
+    /// Creates a profiler for `program`, naming its functions after the
+    /// `let`s they are bound to.
+    pub fn new(program: &Term) -> Self {
+        let mut names = HashMap::new();
+        collect_names(program, &mut names);
+
+        let root = Node {
+            function: MAIN,
+            parent: 0,
+            children: HashMap::new(),
+            exclusive: Duration::ZERO,
+        };
+
+        Self {
+            state: Rc::new(RefCell::new(State {
+                names,
+                ids: HashMap::new(),
+                functions: Vec::new(),
+                nodes: vec![root],
+                stack: Vec::new(),
+            })),
+        }
+    }
+
+    /// Counts a call of the function `id` defined at `location`, returning
+    /// its index in the profile.
+    fn count(state: &mut State, id: usize, location: &Location) -> usize {
+        if let Some(index) = state.ids.get(&id) {
+            state.functions[*index].profile.calls += 1;
+            return *index;
+        }
+
+        let name = match id {
+            MAIN => String::from("<main>"),
+            _ => state
+                .names
+                .get(location)
+                .cloned()
+                .unwrap_or_else(|| String::from("<anonymous>")),
+        };
+
+        state.functions.push(Function {
+            profile: FunctionProfile {
+                name,
+                location: location.clone(),
+                calls: 1,
+                cache_hits: 0,
+                inclusive: Duration::ZERO,
+                exclusive: Duration::ZERO,
+            },
+            running: 0,
+        });
+        state.ids.insert(id, state.functions.len() - 1);
+
+        state.functions.len() - 1
+    }
+
+    /// Starts a call of the function `id` defined at `location`.
+    pub(crate) fn enter(&self, id: usize, location: &Location) {
+        let state = &mut *self.state.borrow_mut();
+        let function = Self::count(state, id, location);
+        state.functions[function].running += 1;
+
+        let parent = state.stack.last().map_or(0, |active| active.node);
+        let node = match state.nodes[parent].children.get(&function) {
+            Some(node) => *node,
+            None => {
+                state.nodes.push(Node {
+                    function,
+                    parent,
+                    children: HashMap::new(),
+                    exclusive: Duration::ZERO,
+                });
+
+                let node = state.nodes.len() - 1;
+                state.nodes[parent].children.insert(function, node);
+                node
+            }
+        };
+
+        state.stack.push(Active {
+            function,
+            node,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Ends the last call started.
+    pub(crate) fn exit(&self) {
+        let state = &mut *self.state.borrow_mut();
+        let Some(active) = state.stack.pop() else {
+            return;
+        };
+
+        let inclusive = active.start.elapsed();
+        let exclusive = inclusive.saturating_sub(active.children);
+
+        let function = &mut state.functions[active.function];
+        function.running -= 1;
+        function.profile.exclusive += exclusive;
+        if function.running == 0 {
+            function.profile.inclusive += inclusive;
+        }
+
+        state.nodes[active.node].exclusive += exclusive;
+        if let Some(caller) = state.stack.last_mut() {
+            caller.children += inclusive;
+        }
+    }
+
+    /// Ends every call still running, after an error stopped the program.
+    pub(crate) fn unwind(&self) {
+        while !self.state.borrow().stack.is_empty() {
+            self.exit();
+        }
+    }
+
+    /// Counts a call of the function `id` answered from the memoization
+    /// cache.
+    pub(crate) fn hit(&self, id: usize, location: &Location) {
+        let state = &mut *self.state.borrow_mut();
+        let function = Self::count(state, id, location);
+
+        state.functions[function].profile.cache_hits += 1;
+    }
+
+    /// Every function called, by decreasing inclusive time.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let state = self.state.borrow();
+        let mut functions: Vec<_> = state
+            .functions
+            .iter()
+            .map(|function| function.profile.clone())
+            .collect();
+
+        functions.sort_by_key(|function| Reverse(function.inclusive));
+        functions
+    }
+
+    /// Lists [`Profiler::functions`] as a table. Locations are written as
+    /// `file:line:column` when `source` is the text they point into.
+    pub fn table(&self, source: Option<&str>) -> String {
+        let header = [
+            "function",
+            "location",
+            "calls",
+            "cache hits",
+            "inclusive",
+            "exclusive",
+        ]
+        .map(String::from);
+
+        let rows: Vec<[String; 6]> = self
+            .functions()
+            .into_iter()
+            .map(|function| {
+                [
+                    function.name,
+                    position(&function.location, source),
+                    function.calls.to_string(),
+                    function.cache_hits.to_string(),
+                    millis(function.inclusive),
+                    millis(function.exclusive),
+                ]
+            })
+            .collect();
+
+        let mut widths = header.clone().map(|column| column.len());
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.chars().count());
+            }
+        }
+
+        let mut table = String::new();
+        for row in std::iter::once(&header).chain(&rows) {
+            let mut line = String::new();
+
+            for (index, column) in row.iter().enumerate() {
+                let width = widths[index];
+
+                // Names and locations are aligned left, numbers right.
+                let _ = match index {
+                    0 | 1 => write!(line, "{column:<width$}  "),
+                    _ => write!(line, "{column:>width$}  "),
+                };
+            }
+
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+
+        table
+    }
+
+    /// The exclusive time of every call stack in nanoseconds, one stack per
+    /// line in the folded format flame graph tools read, like
+    /// `<main>;fib;fib 1200`.
+    pub fn folded(&self, source: Option<&str>) -> String {
+        let state = self.state.borrow();
+        let frame = |node: &Node| {
+            let profile = &state.functions[node.function].profile;
+            format!("{}@{}", profile.name, position(&profile.location, source))
+        };
+
+        let mut lines = Vec::new();
+        for node in state.nodes.iter().skip(1) {
+            let nanos = node.exclusive.as_nanos();
+            if nanos == 0 {
+                continue;
+            }
+
+            let mut stack = vec![frame(node)];
+            let mut parent = node.parent;
+            while parent != 0 {
+                stack.push(frame(&state.nodes[parent]));
+                parent = state.nodes[parent].parent;
+            }
+            stack.reverse();
+
+            lines.push(format!("{} {nanos}\n", stack.join(";")));
+        }
+
+        lines.sort();
+        lines.concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::Location,
+        interpreter::{eval_profiled, Cache, Capture, Context, Options},
+        parser::parse,
+    };
+
+    use super::{Profiler, MAIN};
+
+    fn location(start: usize) -> Location {
+        Location::new(start, start + 1, "tests")
+    }
+
+    #[test]
+    fn names_functions_after_their_let() {
+        let source = "let f = fn () => { fn () => { 1 } };\nf()()";
+        let term = parse(source, "tests").unwrap().expression;
+        let profiler = Profiler::new(&term);
+
+        eval_profiled(
+            Box::new(term),
+            Options::default(),
+            &profiler,
+            &mut Context::new(),
+            &mut Cache::new(),
+            &mut Capture::new(),
+        )
+        .unwrap();
+
+        let mut names: Vec<_> = profiler
+            .functions()
+            .into_iter()
+            .map(|function| (function.name, function.calls))
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                (String::from("<anonymous>"), 1),
+                (String::from("<main>"), 1),
+                (String::from("f"), 1)
+            ]
+        );
+
+        // Columns are separated by at least two spaces, whatever their width.
+        let table = profiler.table(Some(source));
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .map(|line| {
+                line.split("  ")
+                    .map(str::trim)
+                    .filter(|column| !column.is_empty())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows[0],
+            [
+                "function",
+                "location",
+                "calls",
+                "cache hits",
+                "inclusive",
+                "exclusive"
+            ],
+            "{table}"
+        );
+        assert!(
+            rows.iter()
+                .any(|row| row.get(..3) == Some(&["f", "tests:1:9", "1"][..])),
+            "{table}"
+        );
+
+        let folded = profiler.folded(Some(source));
+        assert!(folded
+            .lines()
+            .all(|line| line.starts_with("<main>@tests:1:1") && !line.contains(";<main>")));
+    }
+
+    #[test]
+    fn counts_recursive_calls_once() {
+        let profiler = Profiler::new(&parse("1", "tests").unwrap().expression);
+
+        profiler.enter(MAIN, &location(0));
+        profiler.enter(1, &location(1));
+        profiler.enter(1, &location(1));
+        profiler.exit();
+        profiler.hit(1, &location(1));
+        profiler.exit();
+        profiler.exit();
+
+        let functions = profiler.functions();
+        let main = &functions[0];
+        let function = &functions[1];
+
+        assert_eq!((function.calls, function.cache_hits), (3, 1));
+        assert!(function.inclusive <= main.inclusive);
+        assert_eq!(
+            main.exclusive + function.exclusive,
+            main.inclusive,
+            "every moment is spent in exactly one function"
+        );
+    }
+
+    #[test]
+    fn unwinds_after_errors() {
+        let profiler = Profiler::new(&parse("1", "tests").unwrap().expression);
+
+        profiler.enter(MAIN, &location(0));
+        profiler.enter(1, &location(1));
+        profiler.unwind();
+
+        assert!(profiler.state.borrow().stack.is_empty());
+        assert_eq!(profiler.functions().len(), 2);
+    }
+}
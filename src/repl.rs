@@ -0,0 +1,200 @@
+use crate::{
+    ast::{Element, Let, Location, Term, Var},
+    builtins,
+    cache::Cache,
+    diagnostic::{self, Diagnostic},
+    interpreter::{eval_with_options, Context, Options, Printer, Value},
+    parser::{self, Input},
+};
+
+/// An interactive session of the tree walker. Definitions are kept in its
+/// [`Context`], so every input sees the globals defined before it.
+pub struct Repl {
+    options: Options,
+    context: Context,
+    cache: Cache,
+    /// Every input evaluated so far, so errors raised inside functions
+    /// defined by earlier inputs can still point into their source.
+    sources: Vec<String>,
+}
+
+/// Whether `source` is the start of a longer input, like an unclosed block,
+/// rather than complete or invalid on its own.
+pub fn is_incomplete(source: &str) -> bool {
+    match parser::parse_input(source, "<repl>") {
+        Ok(_) => false,
+        Err(err) => err.location.start >= source.trim_end().len(),
+    }
+}
+
+impl Repl {
+    pub fn new(options: Options, cache: Cache) -> Self {
+        Self {
+            options,
+            context: builtins::context(),
+            cache,
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    /// Evaluates one input, returning its value, or `None` when it defines a
+    /// global. Errors are returned rendered against the input they point to.
+    pub fn eval<I: Printer>(&mut self, source: &str, io: &mut I) -> Result<Option<Value>, String> {
+        let filename = format!("<repl:{}>", self.sources.len() + 1);
+        self.sources.push(source.to_string());
+
+        let input = parser::parse_input(source, &filename).map_err(|err| self.report(&err))?;
+        let (name, term) = match input {
+            // Like a `let` in a file, the definition is in scope of its own
+            // value, so recursive functions resolve and memoize the same.
+            Input::Let(name, value) => {
+                let location = Location::new(name.location.start, value.location().end, &filename);
+                let term = Term::Let(Let {
+                    name: name.clone(),
+                    value: Box::new(value),
+                    next: Box::new(Term::Var(Var::new(&name.text, name.location.clone()))),
+                    location,
+                });
+
+                (Some(name), term)
+            }
+            Input::Term(term) => (None, term),
+        };
+
+        let value = eval_with_options(
+            Box::new(term),
+            self.options,
+            &mut self.context,
+            &mut self.cache,
+            io,
+        )
+        .map_err(|err| self.report(&err))?;
+
+        match name {
+            Some(name) => {
+                self.context.insert(name.text, value);
+                Ok(None)
+            }
+            None => Ok(Some(value)),
+        }
+    }
+
+    fn report<D: Diagnostic>(&self, diagnostic: &D) -> String {
+        diagnostic::render_with(diagnostic, |location| {
+            let input = location
+                .filename
+                .strip_prefix("<repl:")?
+                .strip_suffix('>')?
+                .parse::<usize>()
+                .ok()?;
+
+            self.sources.get(input.checked_sub(1)?).map(String::as_str)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache::Cache,
+        interpreter::{Options, Printer, Value},
+    };
+
+    use super::{is_incomplete, Repl};
+
+    #[derive(Default)]
+    struct DummyIO(String);
+
+    impl Printer for DummyIO {
+        fn print(&mut self, value: Value) -> Value {
+            self.0.push_str(&format!("{value}\n"));
+            value
+        }
+    }
+
+    fn repl() -> Repl {
+        Repl::new(Options::default(), Cache::new())
+    }
+
+    fn eval(repl: &mut Repl, source: &str) -> Result<Option<String>, String> {
+        let value = repl.eval(source, &mut DummyIO::default())?;
+
+        Ok(value.map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn keeps_definitions() {
+        let mut repl = repl();
+
+        assert_eq!(eval(&mut repl, "let x = 40"), Ok(None));
+        assert_eq!(eval(&mut repl, "let y = x + 1;"), Ok(None));
+        assert_eq!(eval(&mut repl, "x + y"), Ok(Some(String::from("81"))));
+        assert!(repl.context().contains_key("x"));
+        assert!(repl.context().contains_key("y"));
+    }
+
+    #[test]
+    fn local_lets_are_not_kept() {
+        let mut repl = repl();
+
+        assert_eq!(eval(&mut repl, "let x = 1; x"), Ok(Some(String::from("1"))));
+        assert!(!repl.context().contains_key("x"));
+    }
+
+    #[test]
+    fn recursive_definitions() {
+        let mut repl = repl();
+
+        eval(
+            &mut repl,
+            "let fib = fn (n) => { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }",
+        )
+        .unwrap();
+
+        assert_eq!(eval(&mut repl, "fib(20)"), Ok(Some(String::from("6765"))));
+        assert!(repl.cache().stats().hits > 0);
+    }
+
+    #[test]
+    fn errors_do_not_end_the_session() {
+        let mut repl = repl();
+
+        let error = eval(&mut repl, "missing + 1").unwrap_err();
+        assert!(error.contains("unbound variable \"missing\""));
+        assert!(error.contains("--> <repl:1>:1:1"));
+
+        assert!(eval(&mut repl, "let 1 = 2").is_err());
+        assert_eq!(eval(&mut repl, "1 + 1"), Ok(Some(String::from("2"))));
+    }
+
+    #[test]
+    fn errors_point_to_the_defining_input() {
+        let mut repl = repl();
+
+        eval(&mut repl, "let f = fn (x) => { x + true }").unwrap();
+        let error = eval(&mut repl, "f(1)").unwrap_err();
+
+        assert!(error.contains("--> <repl:1>:1:21"));
+        assert!(error.contains("1 | let f = fn (x) => { x + true }"));
+
+        let error = eval(&mut repl, "f(1, 2)").unwrap_err();
+        assert!(error.contains("--> <repl:3>:1:1"));
+        assert!(error.contains("1 | let f = fn (x) => { x + true }"));
+    }
+
+    #[test]
+    fn incomplete_inputs() {
+        assert!(is_incomplete("let f = fn (x) => {"));
+        assert!(is_incomplete("if (true) { 1 } else"));
+        assert!(!is_incomplete("1 + 1"));
+        assert!(!is_incomplete("1 1"));
+    }
+}
@@ -0,0 +1,249 @@
+use std::rc::Rc;
+
+use crate::ast::{Address, Term, Var};
+
+/// Bindings of a single function body. Every parameter and `let` gets its
+/// own slot, so a frame never reuses a slot for two different variables.
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<(String, usize)>,
+    slots: usize,
+    free: Vec<Var>,
+}
+
+impl Scope {
+    fn capture(&mut self, var: &Var, address: Option<Address>) {
+        let captured = self
+            .free
+            .iter()
+            .any(|free| free.address == address && free.text == var.text);
+
+        if !captured {
+            let mut free = Var::new(&var.text, var.location.clone());
+            free.address = address;
+            self.free.push(free);
+        }
+    }
+
+    fn declare(&mut self, var: &mut Var) {
+        let slot = self.slots;
+        self.slots += 1;
+        self.bindings.push((var.text.clone(), slot));
+
+        var.address = Some(Address { depth: 0, slot });
+    }
+}
+
+/// Assigns an [`Address`] to every variable bound by a parameter or a
+/// `let`, and lists the free variables of every function. Variables left
+/// without an address are looked up by name in the global
+/// [`crate::interpreter::Context`].
+pub fn resolve(term: &mut Term) {
+    Resolver {
+        scopes: vec![Scope::default()],
+    }
+    .resolve(term)
+}
+
+struct Resolver {
+    scopes: Vec<Scope>,
+}
+
+impl Resolver {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("resolver scope")
+    }
+
+    fn lookup(&mut self, var: &Var) -> Option<Address> {
+        let current = self.scopes.len() - 1;
+        let found = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, scope)| {
+                scope
+                    .bindings
+                    .iter()
+                    .rev()
+                    .find(|(bound, _)| *bound == var.text)
+                    .map(|(_, slot)| (index, *slot))
+            });
+
+        // Every function between the binding and the use captures the
+        // variable, as seen from the frame that function is defined in.
+        let (first, address) = match found {
+            Some((index, slot)) => (index + 1, Some(Address { depth: 0, slot })),
+            None => (1, None),
+        };
+
+        for scope in first..=current {
+            let address = address.map(|address| Address {
+                depth: scope - first,
+                ..address
+            });
+
+            self.scopes[scope].capture(var, address);
+        }
+
+        found.map(|(index, slot)| Address {
+            depth: current - index,
+            slot,
+        })
+    }
+
+    fn resolve(&mut self, term: &mut Term) {
+        match term {
+            Term::Int(_) | Term::Str(_) | Term::Bool(_) => {}
+            Term::Var(var) => var.address = self.lookup(var),
+            Term::Function(function) => {
+                let function = Rc::make_mut(function);
+                self.scopes.push(Scope::default());
+
+                for parameter in &mut function.parameters {
+                    self.scope().declare(parameter);
+                }
+                self.resolve(&mut function.value);
+
+                let scope = self.scopes.pop().expect("function scope");
+                function.free = scope.free;
+            }
+            Term::Let(let_) => {
+                let depth = self.scope().bindings.len();
+
+                // Functions bound by a let can refer to themselves, every
+                // other value only sees the previous bindings.
+                match *let_.value {
+                    Term::Function(_) => {
+                        self.scope().declare(&mut let_.name);
+                        self.resolve(&mut let_.value);
+                    }
+                    _ => {
+                        self.resolve(&mut let_.value);
+                        self.scope().declare(&mut let_.name);
+                    }
+                }
+
+                self.resolve(&mut let_.next);
+                self.scope().bindings.truncate(depth);
+            }
+            Term::If(if_) => {
+                self.resolve(&mut if_.condition);
+                self.resolve(&mut if_.then);
+                self.resolve(&mut if_.otherwise);
+            }
+            Term::Call(call) => {
+                self.resolve(&mut call.callee);
+                call.arguments
+                    .iter_mut()
+                    .for_each(|argument| self.resolve(argument));
+            }
+            Term::Binary(binary) => {
+                self.resolve(&mut binary.lhs);
+                self.resolve(&mut binary.rhs);
+            }
+            Term::Tuple(tuple) => {
+                self.resolve(&mut tuple.first);
+                self.resolve(&mut tuple.second);
+            }
+            Term::First(first) => self.resolve(&mut first.value),
+            Term::Second(second) => self.resolve(&mut second.value),
+            Term::Print(print) => self.resolve(&mut print.value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{Address, Term, Var},
+        parser::parse,
+    };
+
+    use super::resolve;
+
+    fn resolved(source: &str) -> Term {
+        let mut term = parse(source, "tests").unwrap().expression;
+        resolve(&mut term);
+
+        term
+    }
+
+    fn address(depth: usize, slot: usize) -> Option<Address> {
+        Some(Address { depth, slot })
+    }
+
+    #[test]
+    fn let_bindings_get_new_slots() {
+        let Term::Let(outer) = resolved("let x = 1; let x = x; x") else {
+            panic!("expected a let term");
+        };
+        let Term::Let(inner) = *outer.next else {
+            panic!("expected a let term");
+        };
+
+        assert_eq!(outer.name.address, address(0, 0));
+        assert!(matches!(*inner.value, Term::Var(ref var) if var.address == address(0, 0)));
+        assert!(matches!(*inner.next, Term::Var(ref var) if var.address == address(0, 1)));
+    }
+
+    #[test]
+    fn captured_variables_point_to_parent_frames() {
+        let Term::Let(let_) = resolved("let f = fn (a) => { fn (b) => { a + b + f } }; g") else {
+            panic!("expected a let term");
+        };
+        let Term::Function(outer) = *let_.value else {
+            panic!("expected a function term");
+        };
+        let Term::Function(ref inner) = *outer.value else {
+            panic!("expected a function term");
+        };
+        let Term::Binary(ref sum) = *inner.value else {
+            panic!("expected a binary term");
+        };
+        let Term::Binary(ref a_plus_b) = *sum.lhs else {
+            panic!("expected a binary term");
+        };
+
+        assert!(matches!(*a_plus_b.lhs, Term::Var(ref var) if var.address == address(1, 0)));
+        assert!(matches!(*a_plus_b.rhs, Term::Var(ref var) if var.address == address(0, 0)));
+        assert!(matches!(*sum.rhs, Term::Var(ref var) if var.address == address(2, 0)));
+        assert!(matches!(*let_.next, Term::Var(ref var) if var.address.is_none()));
+    }
+
+    #[test]
+    fn functions_list_their_free_variables() {
+        let Term::Let(let_) = resolved("let k = 1; fn (a) => { fn (b) => { a + b + k + g } }")
+        else {
+            panic!("expected a let term");
+        };
+        let Term::Function(outer) = *let_.next else {
+            panic!("expected a function term");
+        };
+        let Term::Function(ref inner) = *outer.value else {
+            panic!("expected a function term");
+        };
+
+        let free = |free: &[Var]| {
+            free.iter()
+                .map(|var| (var.text.clone(), var.address))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            free(&outer.free),
+            [
+                (String::from("k"), address(0, 0)),
+                (String::from("g"), None)
+            ]
+        );
+        assert_eq!(
+            free(&inner.free),
+            [
+                (String::from("a"), address(0, 0)),
+                (String::from("k"), address(1, 0)),
+                (String::from("g"), None),
+            ]
+        );
+    }
+}
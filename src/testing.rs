@@ -0,0 +1,262 @@
+use std::{
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{File, Term},
+    builtins, diagnostic,
+    input::Reader,
+    interpreter::{Capture, Context, RuntimeError, Value},
+    parser,
+};
+
+/// Extension of the files holding what a program is expected to print.
+pub const EXPECTED_EXTENSION: &str = "expected";
+
+/// Exit status of a program that fails while running, like `lipsum run`.
+pub const RUNTIME_STATUS: u8 = 1;
+
+/// Exit status of a program that doesn't load, like `lipsum run`.
+pub const INVALID_STATUS: u8 = 3;
+
+/// What a program is expected to do, read from its `.expected` file: the
+/// values it prints, one per line, and its exit status. The status is 0
+/// unless the last line is `exit: <status>`. The last line needs no line
+/// ending, like every value printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expected {
+    pub output: String,
+    pub status: u8,
+}
+
+impl Expected {
+    pub fn parse(text: &str) -> Self {
+        let body = text.strip_suffix('\n').unwrap_or(text);
+        let (output, last) = match body.rsplit_once('\n') {
+            Some((output, last)) => (format!("{output}\n"), last),
+            None => (String::new(), body),
+        };
+
+        match last.strip_prefix("exit: ").map(str::parse) {
+            Some(Ok(status)) => Self { output, status },
+            _ => Self {
+                output: match body.is_empty() {
+                    true => String::new(),
+                    false => format!("{body}\n"),
+                },
+                status: 0,
+            },
+        }
+    }
+}
+
+/// What running a program did. `error` is the rendered diagnostic of a
+/// failed program, shown in reports but never compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub output: String,
+    pub status: u8,
+    pub error: Option<String>,
+}
+
+/// Globals of a tested program: the builtins, with no arguments and
+/// nothing to read, so runs don't depend on the terminal.
+pub fn context() -> Context {
+    let mut context = builtins::context();
+    for native in builtins::input(Reader::new(io::empty())) {
+        context.insert(native.name().to_string(), Value::from(native));
+    }
+
+    context
+}
+
+/// Programs in `dir` with their `.expected` file, sorted by path. Both the
+/// `.rinha` source and the `.json` AST of a program are compared against
+/// the same file.
+pub fn discover(dir: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut programs = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        if matches!(extension, Some("rinha" | "json")) {
+            let expected = path.with_extension(EXPECTED_EXTENSION);
+            programs.push((path, expected));
+        }
+    }
+
+    programs.sort();
+
+    Ok(programs)
+}
+
+/// Loads the program at `path` and evaluates it with `eval`, which prints
+/// into the given capture.
+pub fn run<E>(path: &Path, eval: E) -> io::Result<Outcome>
+where
+    E: FnOnce(Term, &mut Capture) -> Result<Value, RuntimeError>,
+{
+    let text = std::fs::read_to_string(path)?;
+    let name = path.display().to_string();
+    let json = path.extension().and_then(|ext| ext.to_str()) == Some("json");
+
+    let loaded: Result<File, String> = match json {
+        true => {
+            serde_json::from_str(&text).map_err(|err| format!("invalid AST at {name}: {err}\n"))
+        }
+        false => parser::parse(&text, &name).map_err(|err| diagnostic::render(&err, Some(&text))),
+    };
+
+    let file = match loaded {
+        Ok(file) => file,
+        Err(error) => {
+            return Ok(Outcome {
+                output: String::new(),
+                status: INVALID_STATUS,
+                error: Some(error),
+            })
+        }
+    };
+
+    let mut capture = Capture::new();
+    let error = eval(file.expression, &mut capture).err().map(|err| {
+        // JSON ASTs point at their rinha source, read like `lipsum run` does.
+        let source = match json {
+            true => std::fs::read_to_string(&err.location.filename).ok(),
+            false => Some(text),
+        };
+
+        diagnostic::render(&err, source.as_deref())
+    });
+
+    Ok(Outcome {
+        output: capture.take(),
+        status: match error {
+            Some(_) => RUNTIME_STATUS,
+            None => 0,
+        },
+        error,
+    })
+}
+
+/// Compares an outcome with what was expected, describing every difference.
+pub fn compare(expected: &Expected, outcome: &Outcome) -> Result<(), String> {
+    let mut report = String::new();
+
+    if expected.status != outcome.status {
+        let _ = writeln!(
+            report,
+            "exit status {}, expected {}",
+            outcome.status, expected.status
+        );
+    }
+
+    if expected.output != outcome.output {
+        let _ = writeln!(report, "output differs (- expected, + actual):");
+        report.push_str(&diff(&expected.output, &outcome.output));
+    }
+
+    if report.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(error) = &outcome.error {
+        report.push_str(error);
+    }
+
+    Err(report)
+}
+
+/// A line diff of two texts, from their longest common subsequence of lines.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+
+    // common[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..].
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = match expected[i] == actual[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            let _ = writeln!(diff, "  {}", expected[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            let _ = writeln!(diff, "- {}", expected[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(diff, "+ {}", actual[j]);
+            j += 1;
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, diff, Expected, Outcome};
+
+    fn outcome(output: &str, status: u8) -> Outcome {
+        Outcome {
+            output: output.to_string(),
+            status,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn parses_expected_files() {
+        let expected = |output: &str, status| Expected {
+            output: output.to_string(),
+            status,
+        };
+
+        assert_eq!(Expected::parse("55\n"), expected("55\n", 0));
+        assert_eq!(Expected::parse("1\n2\nexit: 1\n"), expected("1\n2\n", 1));
+        assert_eq!(Expected::parse("exit: 3"), expected("", 3));
+        assert_eq!(Expected::parse("exit: a\n"), expected("exit: a\n", 0));
+    }
+
+    #[test]
+    fn expects_a_final_line_ending() {
+        let expected = Expected::parse("1\n2");
+
+        assert_eq!(expected.output, "1\n2\n");
+        assert!(compare(&expected, &outcome("1\n2\n", 0)).is_ok());
+    }
+
+    #[test]
+    fn compares_output_and_status() {
+        let expected = Expected::parse("1\n2\n");
+
+        assert!(compare(&expected, &outcome("1\n2\n", 0)).is_ok());
+        assert_eq!(
+            compare(&expected, &outcome("1\n", 1)).unwrap_err(),
+            "exit status 1, expected 0\noutput differs (- expected, + actual):\n  1\n- 2\n"
+        );
+    }
+
+    #[test]
+    fn diffs_lines() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nx\nc\nd\n"),
+            "  a\n- b\n+ x\n  c\n+ d\n"
+        );
+        assert_eq!(diff("", "a\n"), "+ a\n");
+    }
+}
@@ -0,0 +1,734 @@
+use std::{collections::HashSet, fmt::Display};
+
+use crate::{
+    ast::{Binary, BinaryOp, Element, Location, Term},
+    builtins,
+    diagnostic::Diagnostic,
+};
+
+/// Static type of a rinha term.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Str,
+    Bool,
+    Tuple(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    Var(usize),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int => f.write_str("int"),
+            Self::Str => f.write_str("str"),
+            Self::Bool => f.write_str("bool"),
+            Self::Tuple(first, second) => write!(f, "({first}, {second})"),
+            Self::Function(parameters, result) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| parameter.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                write!(f, "fn({parameters}) -> {result}")
+            }
+            Self::Var(var) => write!(f, "t{var}"),
+        }
+    }
+}
+
+impl Type {
+    fn free_vars(&self, vars: &mut HashSet<usize>) {
+        match self {
+            Self::Int | Self::Str | Self::Bool => {}
+            Self::Tuple(first, second) => {
+                first.free_vars(vars);
+                second.free_vars(vars);
+            }
+            Self::Function(parameters, result) => {
+                parameters
+                    .iter()
+                    .for_each(|parameter| parameter.free_vars(vars));
+                result.free_vars(vars);
+            }
+            Self::Var(var) => {
+                vars.insert(*var);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeError {
+    pub message: String,
+    pub full_text: String,
+    pub location: Location,
+}
+
+impl Diagnostic for TypeError {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn full_text(&self) -> &str {
+        &self.full_text
+    }
+
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// A type generalized over the variables in `vars`.
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+/// Checks that can only be decided once the operand types are known.
+#[derive(Debug, Clone)]
+enum Constraint {
+    /// `+` works on ints, and on strings concatenated with strings or ints.
+    Add {
+        lhs: Type,
+        rhs: Type,
+        result: Type,
+        location: Location,
+    },
+    /// Only ints, strings and booleans can be compared.
+    Comparable { ty: Type, location: Location },
+}
+
+/// Hindley–Milner type inference over [`Term`], collecting every error
+/// instead of stopping at the first one.
+#[derive(Default)]
+pub struct TypeChecker {
+    substitution: Vec<Option<Type>>,
+    environment: Vec<(String, Scheme)>,
+    pending: Vec<Constraint>,
+    errors: Vec<TypeError>,
+}
+
+/// Infers the type of a whole program, returning every type error found.
+/// Builtins are in scope, like when the program runs.
+pub fn check(term: &Term) -> Result<Type, Vec<TypeError>> {
+    let mut checker = TypeChecker::default();
+
+    for (name, ty) in builtins::types() {
+        let scheme = checker.builtin(ty);
+        checker.environment.push((name.to_string(), scheme));
+    }
+
+    let ty = checker.infer(term);
+    checker.solve_pending(true, &HashSet::new());
+
+    match checker.errors.is_empty() {
+        true => Ok(checker.resolve(&ty)),
+        false => Err(checker.errors),
+    }
+}
+
+impl TypeChecker {
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    fn prune(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.substitution[*var] {
+                Some(bound) => self.prune(bound),
+                None => ty.clone(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    /// Fully applies the current substitution to `ty`.
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.prune(ty) {
+            Type::Tuple(first, second) => Type::Tuple(
+                Box::new(self.resolve(&first)),
+                Box::new(self.resolve(&second)),
+            ),
+            Type::Function(parameters, result) => Type::Function(
+                parameters
+                    .iter()
+                    .map(|parameter| self.resolve(parameter))
+                    .collect(),
+                Box::new(self.resolve(&result)),
+            ),
+            ty => ty,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Type) -> bool {
+        let mut vars = HashSet::new();
+        self.resolve(ty).free_vars(&mut vars);
+
+        vars.contains(&var)
+    }
+
+    fn unify(&mut self, lhs: &Type, rhs: &Type) -> Result<(), ()> {
+        match (self.prune(lhs), self.prune(rhs)) {
+            (Type::Var(l_var), Type::Var(r_var)) if l_var == r_var => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => match self.occurs(var, &ty) {
+                true => Err(()),
+                false => {
+                    self.substitution[var] = Some(ty);
+                    Ok(())
+                }
+            },
+            (Type::Int, Type::Int) | (Type::Str, Type::Str) | (Type::Bool, Type::Bool) => Ok(()),
+            (Type::Tuple(l_first, l_second), Type::Tuple(r_first, r_second)) => {
+                self.unify(&l_first, &r_first)?;
+                self.unify(&l_second, &r_second)
+            }
+            (Type::Function(l_params, l_result), Type::Function(r_params, r_result))
+                if l_params.len() == r_params.len() =>
+            {
+                for (l_param, r_param) in l_params.iter().zip(r_params.iter()) {
+                    self.unify(l_param, r_param)?;
+                }
+
+                self.unify(&l_result, &r_result)
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn error(&mut self, message: &str, full_text: String, location: &Location) {
+        self.errors.push(TypeError {
+            message: message.to_string(),
+            full_text,
+            location: location.clone(),
+        });
+    }
+
+    /// Unifies `found` with `expected`, reporting a mismatch at `location`.
+    fn expect(&mut self, expected: &Type, found: &Type, location: &Location) {
+        if self.unify(expected, found).is_err() {
+            let expected = self.resolve(expected);
+            let found = self.resolve(found);
+
+            self.error(
+                "type mismatch",
+                format!("expected {expected}, found {found}"),
+                location,
+            );
+        }
+    }
+
+    fn environment_vars(&self) -> HashSet<usize> {
+        let mut vars = HashSet::new();
+
+        for (_, scheme) in &self.environment {
+            let mut scheme_vars = HashSet::new();
+            self.resolve(&scheme.ty).free_vars(&mut scheme_vars);
+
+            vars.extend(
+                scheme_vars
+                    .into_iter()
+                    .filter(|var| !scheme.vars.contains(var)),
+            );
+        }
+
+        vars
+    }
+
+    fn generalize(&mut self, ty: &Type) -> Scheme {
+        let environment_vars = self.environment_vars();
+        self.solve_pending(true, &environment_vars);
+
+        let ty = self.resolve(ty);
+        let mut vars = HashSet::new();
+        ty.free_vars(&mut vars);
+
+        Scheme {
+            vars: vars
+                .into_iter()
+                .filter(|var| !environment_vars.contains(var))
+                .collect(),
+            ty,
+        }
+    }
+
+    /// Generalizes the type of a builtin over all of its variables, renamed
+    /// to fresh ones so they never clash with the program's.
+    fn builtin(&mut self, ty: Type) -> Scheme {
+        let mut vars = HashSet::new();
+        ty.free_vars(&mut vars);
+
+        let ty = self.instantiate(&Scheme {
+            vars: vars.into_iter().collect(),
+            ty,
+        });
+
+        let mut vars = HashSet::new();
+        ty.free_vars(&mut vars);
+
+        Scheme {
+            vars: vars.into_iter().collect(),
+            ty,
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mapping: Vec<(usize, Type)> =
+            scheme.vars.iter().map(|var| (*var, self.fresh())).collect();
+
+        fn replace(ty: &Type, mapping: &[(usize, Type)]) -> Type {
+            match ty {
+                Type::Var(var) => mapping
+                    .iter()
+                    .find(|(from, _)| from == var)
+                    .map_or(ty.clone(), |(_, to)| to.clone()),
+                Type::Tuple(first, second) => Type::Tuple(
+                    Box::new(replace(first, mapping)),
+                    Box::new(replace(second, mapping)),
+                ),
+                Type::Function(parameters, result) => Type::Function(
+                    parameters
+                        .iter()
+                        .map(|parameter| replace(parameter, mapping))
+                        .collect(),
+                    Box::new(replace(result, mapping)),
+                ),
+                ty => ty.clone(),
+            }
+        }
+
+        replace(&self.resolve(&scheme.ty), &mapping)
+    }
+
+    /// Tries to decide a constraint. Undecided constraints are kept unless
+    /// `force` is set, in which case ambiguous additions default to ints.
+    fn solve(&mut self, constraint: &Constraint, force: bool) -> bool {
+        match constraint {
+            Constraint::Add {
+                lhs,
+                rhs,
+                result,
+                location,
+            } => match (self.prune(lhs), self.prune(rhs)) {
+                (Type::Int, Type::Int) => {
+                    self.expect(&Type::Int, result, location);
+                    true
+                }
+                (Type::Str, Type::Str | Type::Int | Type::Var(_))
+                | (Type::Int | Type::Var(_), Type::Str) => {
+                    self.expect(&Type::Str, result, location);
+                    true
+                }
+                (l_ty @ (Type::Bool | Type::Tuple(..) | Type::Function(..)), r_ty)
+                | (l_ty, r_ty @ (Type::Bool | Type::Tuple(..) | Type::Function(..))) => {
+                    let (l_ty, r_ty) = (self.resolve(&l_ty), self.resolve(&r_ty));
+                    self.error(
+                        "invalid addition",
+                        format!("{l_ty} cannot be added to {r_ty}"),
+                        location,
+                    );
+                    true
+                }
+                _ if force => {
+                    self.expect(&Type::Int, lhs, location);
+                    self.expect(&Type::Int, rhs, location);
+                    self.expect(&Type::Int, result, location);
+                    true
+                }
+                _ => false,
+            },
+            Constraint::Comparable { ty, location } => match self.prune(ty) {
+                Type::Tuple(..) | Type::Function(..) => {
+                    let ty = self.resolve(ty);
+                    self.error(
+                        "invalid comparison",
+                        format!("values of type {ty} cannot be compared"),
+                        location,
+                    );
+                    true
+                }
+                Type::Var(_) => force,
+                _ => true,
+            },
+        }
+    }
+
+    /// Solves the pending constraints that don't mention any of the
+    /// `environment_vars`, which may still be refined by the enclosing scope.
+    fn solve_pending(&mut self, force: bool, environment_vars: &HashSet<usize>) {
+        let pending = std::mem::take(&mut self.pending);
+
+        for constraint in pending {
+            let mut vars = HashSet::new();
+            match &constraint {
+                Constraint::Add { lhs, rhs, .. } => {
+                    self.resolve(lhs).free_vars(&mut vars);
+                    self.resolve(rhs).free_vars(&mut vars);
+                }
+                Constraint::Comparable { ty, .. } => self.resolve(ty).free_vars(&mut vars),
+            }
+
+            let force = force && vars.is_disjoint(environment_vars);
+            if !self.solve(&constraint, force) {
+                self.pending.push(constraint);
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Scheme> {
+        self.environment
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == name)
+            .map(|(_, scheme)| scheme.clone())
+    }
+
+    fn infer_binary(&mut self, binary: &Binary) -> Type {
+        let lhs = self.infer(&binary.lhs);
+        let rhs = self.infer(&binary.rhs);
+
+        match binary.op {
+            BinaryOp::Add => {
+                let result = self.fresh();
+                let constraint = Constraint::Add {
+                    lhs,
+                    rhs,
+                    result: result.clone(),
+                    location: binary.location.clone(),
+                };
+
+                if !self.solve(&constraint, false) {
+                    self.pending.push(constraint);
+                }
+
+                result
+            }
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                self.expect(&Type::Int, &lhs, binary.lhs.location());
+                self.expect(&Type::Int, &rhs, binary.rhs.location());
+                Type::Int
+            }
+            BinaryOp::Eq
+            | BinaryOp::Neq
+            | BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Lte
+            | BinaryOp::Gte => {
+                self.expect(&lhs, &rhs, binary.rhs.location());
+
+                let constraint = Constraint::Comparable {
+                    ty: lhs,
+                    location: binary.location.clone(),
+                };
+                if !self.solve(&constraint, false) {
+                    self.pending.push(constraint);
+                }
+
+                Type::Bool
+            }
+            BinaryOp::And | BinaryOp::Or => {
+                self.expect(&Type::Bool, &lhs, binary.lhs.location());
+                self.expect(&Type::Bool, &rhs, binary.rhs.location());
+                Type::Bool
+            }
+        }
+    }
+
+    pub fn infer(&mut self, term: &Term) -> Type {
+        match term {
+            Term::Int(_) => Type::Int,
+            Term::Str(_) => Type::Str,
+            Term::Bool(_) => Type::Bool,
+            Term::Var(var) => match self.lookup(&var.text) {
+                Some(scheme) => self.instantiate(&scheme),
+                None => {
+                    self.error(
+                        &format!("unbound variable \"{}\"", var.text),
+                        format!(
+                            "variable \"{}\" was not defined in the current scope",
+                            var.text
+                        ),
+                        &var.location,
+                    );
+                    self.fresh()
+                }
+            },
+            Term::Function(function) => {
+                let scope = self.environment.len();
+                let mut parameters = Vec::new();
+
+                for parameter in &function.parameters {
+                    let ty = self.fresh();
+                    parameters.push(ty.clone());
+                    self.environment.push((
+                        parameter.text.clone(),
+                        Scheme {
+                            vars: Vec::new(),
+                            ty,
+                        },
+                    ));
+                }
+
+                let result = self.infer(&function.value);
+                self.environment.truncate(scope);
+
+                Type::Function(parameters, Box::new(result))
+            }
+            Term::Call(call) => {
+                let callee = self.infer(&call.callee);
+                let arguments: Vec<Type> = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.infer(argument))
+                    .collect();
+
+                let result = self.fresh();
+                let expected = Type::Function(arguments, Box::new(result.clone()));
+
+                if self.unify(&callee, &expected).is_err() {
+                    match self.resolve(&callee) {
+                        Type::Function(parameters, _)
+                            if parameters.len() != call.arguments.len() =>
+                        {
+                            self.error(
+                                "arity mismatch",
+                                format!(
+                                    "function expects {} arguments but {} were given",
+                                    parameters.len(),
+                                    call.arguments.len()
+                                ),
+                                &call.location,
+                            )
+                        }
+                        callee @ Type::Function(..) => {
+                            let expected = self.resolve(&expected);
+                            self.error(
+                                "type mismatch",
+                                format!("expected {callee}, found {expected}"),
+                                &call.location,
+                            )
+                        }
+                        // A variable only fails to unify when calling it
+                        // would make its type contain itself.
+                        callee @ Type::Var(_) => {
+                            let expected = self.resolve(&expected);
+                            self.error(
+                                "infinite type",
+                                format!("{callee} cannot be {expected}, a type containing itself"),
+                                &call.location,
+                            )
+                        }
+                        callee => self.error(
+                            "invalid function call",
+                            format!("{callee} cannot be called as a function"),
+                            &call.location,
+                        ),
+                    }
+                }
+
+                result
+            }
+            Term::Let(let_) => {
+                let ty = match *let_.value {
+                    // Functions bound by a let can call themselves.
+                    Term::Function(_) => {
+                        let ty = self.fresh();
+                        self.environment.push((
+                            let_.name.text.clone(),
+                            Scheme {
+                                vars: Vec::new(),
+                                ty: ty.clone(),
+                            },
+                        ));
+
+                        let value = self.infer(&let_.value);
+                        self.environment.pop();
+                        self.expect(&ty, &value, let_.value.location());
+
+                        ty
+                    }
+                    _ => self.infer(&let_.value),
+                };
+
+                let scheme = self.generalize(&ty);
+                self.environment.push((let_.name.text.clone(), scheme));
+                let next = self.infer(&let_.next);
+                self.environment.pop();
+
+                next
+            }
+            Term::If(if_) => {
+                let condition = self.infer(&if_.condition);
+                if self.unify(&Type::Bool, &condition).is_err() {
+                    let condition = self.resolve(&condition);
+                    self.error(
+                        "invalid if condition",
+                        format!(
+                            "{condition} can't be used as an if condition. use a boolean instead"
+                        ),
+                        if_.condition.location(),
+                    );
+                }
+
+                let then = self.infer(&if_.then);
+                let otherwise = self.infer(&if_.otherwise);
+                self.expect(&then, &otherwise, if_.otherwise.location());
+
+                then
+            }
+            Term::Binary(binary) => self.infer_binary(binary),
+            Term::Print(print) => self.infer(&print.value),
+            Term::First(first) => {
+                let value = self.infer(&first.value);
+                let (l_ty, r_ty) = (self.fresh(), self.fresh());
+                let tuple = Type::Tuple(Box::new(l_ty.clone()), Box::new(r_ty));
+
+                if self.unify(&tuple, &value).is_err() {
+                    let value = self.resolve(&value);
+                    self.error(
+                        "invalid expression",
+                        format!("cannot use first operation on {value}, only on a tuple"),
+                        &first.location,
+                    );
+                }
+
+                l_ty
+            }
+            Term::Second(second) => {
+                let value = self.infer(&second.value);
+                let (l_ty, r_ty) = (self.fresh(), self.fresh());
+                let tuple = Type::Tuple(Box::new(l_ty), Box::new(r_ty.clone()));
+
+                if self.unify(&tuple, &value).is_err() {
+                    let value = self.resolve(&value);
+                    self.error(
+                        "invalid expression",
+                        format!("cannot use second operation on {value}, only on a tuple"),
+                        &second.location,
+                    );
+                }
+
+                r_ty
+            }
+            Term::Tuple(tuple) => {
+                let first = self.infer(&tuple.first);
+                let second = self.infer(&tuple.second);
+
+                Type::Tuple(Box::new(first), Box::new(second))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+
+    use super::{check, Type, TypeError};
+
+    fn infer(source: &str) -> Result<Type, Vec<TypeError>> {
+        check(&parse(source, "tests").unwrap().expression)
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        infer(source)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
+
+    #[test]
+    fn examples() {
+        let examples = [
+            include_str!("../examples/fib.rinha"),
+            include_str!("../examples/sum.rinha"),
+            include_str!("../examples/combination.rinha"),
+        ];
+
+        for source in examples {
+            assert_eq!(infer(source).unwrap(), Type::Int);
+        }
+
+        let hello_world = include_str!("../examples/hello-world.rinha");
+        assert_eq!(infer(hello_world).unwrap(), Type::Str);
+    }
+
+    #[test]
+    fn string_concatenation() {
+        assert_eq!(infer("\"a\" + 1").unwrap(), Type::Str);
+        assert_eq!(infer("fn (s) => { s + \"!\" }(\"hi\")").unwrap(), Type::Str);
+    }
+
+    #[test]
+    fn let_polymorphism() {
+        let source = "let id = fn (x) => { x }; (id(1), id(\"a\"))";
+
+        assert_eq!(
+            infer(source).unwrap(),
+            Type::Tuple(Box::new(Type::Int), Box::new(Type::Str))
+        );
+    }
+
+    #[test]
+    fn invalid_subtraction() {
+        let errors = infer("\"a\" - 1").unwrap_err();
+
+        assert_eq!(errors[0].message, "type mismatch");
+        assert_eq!(errors[0].location.start, 0);
+        assert_eq!(errors[0].location.end, 3);
+    }
+
+    #[test]
+    fn invalid_if_condition() {
+        assert_eq!(
+            messages("if (1) { 1 } else { 2 }"),
+            ["invalid if condition"]
+        );
+    }
+
+    #[test]
+    fn first_of_non_tuple() {
+        assert_eq!(messages("first(5)"), ["invalid expression"]);
+    }
+
+    #[test]
+    fn call_non_closure() {
+        assert_eq!(messages("let x = 1; x(2)"), ["invalid function call"]);
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        assert_eq!(messages("fn (a, b) => { a }(1)"), ["arity mismatch"]);
+    }
+
+    #[test]
+    fn compare_tuples() {
+        assert_eq!(messages("(1, 2) == (1, 2)"), ["invalid comparison"]);
+    }
+
+    #[test]
+    fn reports_every_error() {
+        assert_eq!(
+            messages("let a = true + 1; let b = second(2); c"),
+            [
+                "invalid addition",
+                "invalid expression",
+                "unbound variable \"c\""
+            ]
+        );
+    }
+
+    #[test]
+    fn self_application_is_an_infinite_type() {
+        assert_eq!(messages("fn (f) => { f(f) }"), ["infinite type"]);
+    }
+
+    #[test]
+    fn recursion_infinite_type() {
+        assert_eq!(messages("let f = fn (x) => { f }; f"), ["type mismatch"]);
+    }
+}
@@ -0,0 +1,715 @@
+use std::{
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use crate::{
+    bytecode::{Capture, Instruction, Program, Prototype},
+    interpreter::{
+        arity_mismatch, next_closure_id, stack_overflow, Cache, Context, Key, Options, Printer,
+        RuntimeError, Tuple, Value,
+    },
+    profiler::{self, Profiler},
+};
+
+/// A closure created by the VM: a compiled function and the values it captured.
+#[derive(Debug)]
+pub struct Closure {
+    id: usize,
+    function: usize,
+    captures: Vec<Value>,
+}
+
+/// Like [`crate::interpreter::Closure`], a closure is only equal to itself.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Closure {}
+
+impl Hash for Closure {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+struct Frame {
+    closure: Option<Rc<Closure>>,
+    function: usize,
+    ip: usize,
+    /// Index of the first local of this frame.
+    base: usize,
+    /// Number of calls running, counting this one. Tail calls replace the
+    /// frame of their caller, keeping its depth.
+    depth: usize,
+    /// Keys the result is memoized under when the frame returns, more than
+    /// one after tail calls.
+    memo: Vec<Key>,
+}
+
+pub struct Vm<'a, I: Printer> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    locals: Vec<Option<Value>>,
+    frames: Vec<Frame>,
+    cache: &'a mut Cache,
+    io: &'a mut I,
+    options: Options,
+    /// Values of the variables the compiler couldn't bind, like builtins.
+    globals: &'a Context,
+    profiler: Option<&'a Profiler>,
+}
+
+/// Runs a compiled program, its unbound variables looked up in `globals`.
+/// Output, memoization and errors match [`crate::interpreter::eval`].
+pub fn run<I: Printer>(
+    program: &Program,
+    globals: &Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    run_with_options(program, Options::default(), globals, cache, io)
+}
+
+/// Like [`run`], matching [`crate::interpreter::eval_with_options`]. Frames
+/// live on the heap, so any depth fits in the native stack.
+pub fn run_with_options<I: Printer>(
+    program: &Program,
+    options: Options,
+    globals: &Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    start(program, options, None, globals, cache, io)
+}
+
+/// Like [`run_with_options`], matching [`crate::interpreter::eval_profiled`].
+pub fn run_profiled<I: Printer>(
+    program: &Program,
+    options: Options,
+    profiler: &Profiler,
+    globals: &Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    profiler.enter(profiler::MAIN, &program.functions[program.main].location);
+    let result = start(program, options, Some(profiler), globals, cache, io);
+    profiler.unwind();
+
+    result
+}
+
+fn start<I: Printer>(
+    program: &Program,
+    options: Options,
+    profiler: Option<&Profiler>,
+    globals: &Context,
+    cache: &mut Cache,
+    io: &mut I,
+) -> Result<Value, RuntimeError> {
+    let mut vm = Vm {
+        program,
+        stack: Vec::new(),
+        locals: Vec::new(),
+        frames: Vec::new(),
+        cache,
+        io,
+        options,
+        globals,
+        profiler,
+    };
+
+    vm.locals
+        .resize(program.functions[program.main].locals, None);
+    vm.frames.push(Frame {
+        closure: None,
+        function: program.main,
+        ip: 0,
+        base: 0,
+        depth: 0,
+        memo: Vec::new(),
+    });
+
+    vm.execute()
+}
+
+/// Builds the memoization key of a call from the function, the arguments
+/// and the values the closure captured.
+fn cache_key(prototype: &Prototype, closure: &Closure, arguments: &[Value]) -> Option<Key> {
+    if !prototype.pure {
+        return None;
+    }
+
+    Some(Key {
+        function: prototype.id,
+        values: arguments.iter().chain(&closure.captures).cloned().collect(),
+    })
+}
+
+/// Whether the code at `ip` returns without doing anything else, making a
+/// call right before it a tail call.
+fn returns_at(code: &[Instruction], mut ip: usize) -> bool {
+    loop {
+        match code[ip] {
+            Instruction::Return => return true,
+            Instruction::Jump(target) => ip = target,
+            _ => return false,
+        }
+    }
+}
+
+impl<'a, I: Printer> Vm<'a, I> {
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("vm stack underflow")
+    }
+
+    fn call(&mut self, argc: usize, location: usize) -> Result<(), RuntimeError> {
+        let arguments = self.stack.split_off(self.stack.len() - argc);
+        let callee = self.pop();
+
+        // The top-level program is never replaced, as its calls run one level
+        // deeper like in the tree walker.
+        let caller = self.frames.last().expect("vm frame");
+        let caller_prototype = &self.program.functions[caller.function];
+
+        let closure = match callee {
+            Value::Compiled(closure) => closure,
+            Value::Native(native) => {
+                if native.is_interactive() {
+                    self.io.show();
+                }
+
+                let value = native
+                    .call(arguments)
+                    .map_err(|err| err.at(&caller_prototype.locations[location]))?;
+                self.stack.push(value);
+
+                return Ok(());
+            }
+            _ => unreachable!("callee is checked before its arguments are evaluated"),
+        };
+        let tail = caller.closure.is_some() && returns_at(&caller_prototype.code, caller.ip);
+        let depth = match tail {
+            true => caller.depth,
+            false => caller.depth + 1,
+        };
+
+        if depth > self.options.max_depth {
+            let location = &caller_prototype.locations[location];
+            return Err(stack_overflow(self.options.max_depth, location));
+        }
+
+        let prototype = &self.program.functions[closure.function];
+        let key = cache_key(prototype, &closure, &arguments);
+
+        if let Some(cached_value) = key.as_ref().and_then(|key| self.cache.get(key)) {
+            if let Some(profiler) = self.profiler {
+                profiler.hit(prototype.id, &prototype.location);
+            }
+
+            self.stack.push(cached_value);
+            return Ok(());
+        }
+
+        let mut memo = match tail {
+            true => {
+                let caller = self.frames.pop().expect("vm frame");
+                self.locals.truncate(caller.base);
+
+                if let Some(profiler) = self.profiler {
+                    profiler.exit();
+                }
+
+                caller.memo
+            }
+            false => Vec::new(),
+        };
+        memo.extend(key);
+
+        let base = self.locals.len();
+        self.locals.resize(base + prototype.locals, None);
+
+        let first_parameter = match prototype.self_slot {
+            Some(slot) => {
+                self.locals[base + slot] = Some(Value::Compiled(closure.clone()));
+                slot + 1
+            }
+            None => 0,
+        };
+
+        for (index, argument) in arguments.into_iter().enumerate() {
+            self.locals[base + first_parameter + index] = Some(argument);
+        }
+
+        self.frames.push(Frame {
+            function: closure.function,
+            closure: Some(closure),
+            ip: 0,
+            base,
+            depth,
+            memo,
+        });
+
+        if let Some(profiler) = self.profiler {
+            profiler.enter(prototype.id, &prototype.location);
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        let program = self.program;
+
+        loop {
+            let frame = self.frames.last_mut().expect("vm frame");
+            let prototype = &program.functions[frame.function];
+            let instruction = &prototype.code[frame.ip];
+            frame.ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    self.stack.push(prototype.constants[*index].clone())
+                }
+                Instruction::Load { slot, name } => match &self.locals[frame.base + slot] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(unbound(prototype, *name)),
+                },
+                Instruction::Capture(index) => {
+                    let closure = frame.closure.as_ref().expect("captures outside a closure");
+                    self.stack.push(closure.captures[*index].clone());
+                }
+                Instruction::Global(name) => match self.globals.get(&prototype.names[*name].text) {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(unbound(prototype, *name)),
+                },
+                Instruction::Store(slot) => {
+                    let slot = frame.base + slot;
+                    self.locals[slot] = self.stack.pop();
+                }
+                Instruction::Closure(index) => {
+                    let captures = program.functions[*index]
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => self.locals[frame.base + slot]
+                                .clone()
+                                .expect("captured locals are bound before the closure"),
+                            Capture::Capture(index) => frame
+                                .closure
+                                .as_ref()
+                                .expect("captures outside a closure")
+                                .captures[*index]
+                                .clone(),
+                        })
+                        .collect();
+
+                    self.stack.push(Value::Compiled(Rc::new(Closure {
+                        id: next_closure_id(),
+                        function: *index,
+                        captures,
+                    })));
+                }
+                Instruction::CheckCallable { argc, location } => match self.stack.last() {
+                    Some(Value::Compiled(closure)) => {
+                        let callee = &program.functions[closure.function];
+
+                        if callee.parameters != *argc {
+                            return Err(arity_mismatch(
+                                callee.parameters,
+                                *argc,
+                                &prototype.locations[*location],
+                                Some(&callee.location),
+                            ));
+                        }
+                    }
+                    Some(Value::Native(native)) => {
+                        if native.arity() != *argc {
+                            return Err(arity_mismatch(
+                                native.arity(),
+                                *argc,
+                                &prototype.locations[*location],
+                                None,
+                            ));
+                        }
+                    }
+                    value => {
+                        let value = value.expect("vm stack underflow");
+
+                        return Err(RuntimeError {
+                            message: String::from("invalid function call"),
+                            full_text: format!("{} cannot be called as a function", value),
+                            location: prototype.locations[*location].clone(),
+                            note: None,
+                        });
+                    }
+                },
+                Instruction::Call { argc, location } => self.call(*argc, *location)?,
+                Instruction::Return => {
+                    let frame = self.frames.pop().expect("vm frame");
+                    self.locals.truncate(frame.base);
+
+                    if let Some(profiler) = self.profiler {
+                        profiler.exit();
+                    }
+
+                    for key in frame.memo {
+                        let value = self.stack.last().expect("vm stack underflow").clone();
+                        self.cache.insert(key, value);
+                    }
+
+                    if self.frames.is_empty() {
+                        return Ok(self.pop());
+                    }
+                }
+                Instruction::Jump(target) => frame.ip = *target,
+                Instruction::JumpIfFalse { target, location } => {
+                    let target = *target;
+
+                    match self.stack.pop().expect("vm stack underflow") {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => frame.ip = target,
+                        condition => {
+                            return Err(RuntimeError {
+                                message: String::from("invalid if condition"),
+                                full_text: format!(
+                                    "{} can't be used as an if condition. use a boolean instead",
+                                    condition
+                                ),
+                                location: prototype.locations[*location].clone(),
+                                note: None,
+                            })
+                        }
+                    }
+                }
+                Instruction::ShortCircuit {
+                    op,
+                    target,
+                    location,
+                } => {
+                    let lhs = self.stack.last().expect("vm stack underflow");
+
+                    if lhs
+                        .short_circuit(op, &prototype.locations[*location])?
+                        .is_some()
+                    {
+                        frame.ip = *target;
+                    }
+                }
+                Instruction::Binary { op, location } => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+
+                    let value = lhs.apply(
+                        op,
+                        &rhs,
+                        &prototype.locations[*location],
+                        self.options.overflow,
+                    )?;
+                    self.stack.push(value);
+                }
+                Instruction::Tuple => {
+                    let second = self.pop();
+                    let first = self.pop();
+
+                    self.stack.push(Value::Tuple(Tuple {
+                        first: Box::new(first),
+                        second: Box::new(second),
+                    }));
+                }
+                Instruction::First(location) => match self.pop() {
+                    Value::Tuple(Tuple { first, second: _ }) => self.stack.push(*first),
+                    _value => {
+                        return Err(RuntimeError {
+                            message: String::from("invalid expression"),
+                            full_text: String::from(
+                                "cannot use first operation from anything but a tuple",
+                            ),
+                            location: prototype.locations[*location].clone(),
+                            note: None,
+                        })
+                    }
+                },
+                Instruction::Second(location) => match self.pop() {
+                    Value::Tuple(Tuple { first: _, second }) => self.stack.push(*second),
+                    _value => {
+                        return Err(RuntimeError {
+                            message: String::from("invalid expression"),
+                            full_text: String::from(
+                                "cannot use second operation from anything but a tuple",
+                            ),
+                            location: prototype.locations[*location].clone(),
+                            note: None,
+                        })
+                    }
+                },
+                Instruction::Print => {
+                    let value = self.pop();
+                    let value = self.io.print(value);
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+}
+
+fn unbound(prototype: &Prototype, name: usize) -> RuntimeError {
+    let var = &prototype.names[name];
+
+    RuntimeError {
+        message: format!("unbound variable \"{}\"", var.text),
+        full_text: format!(
+            "variable \"{}\" was not defined in the current scope",
+            var.text
+        ),
+        location: var.location.clone(),
+        note: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        binary::Overflow,
+        builtins,
+        bytecode::compile,
+        interpreter::{
+            eval_profiled, eval_with_options, Cache, Options, Printer, RuntimeError, Value,
+        },
+        parser::parse,
+        profiler::Profiler,
+    };
+
+    use super::{run_profiled, run_with_options};
+
+    const MAX_DEPTH: usize = 100;
+
+    fn options(max_depth: usize) -> Options {
+        Options {
+            max_depth,
+            ..Options::default()
+        }
+    }
+
+    #[derive(Default)]
+    struct DummyIO(String);
+
+    impl Printer for DummyIO {
+        fn print(&mut self, value: Value) -> Value {
+            self.0.push_str(&format!("{}\n", value));
+
+            value
+        }
+    }
+
+    fn tree_walker(source: &str, max_depth: usize) -> (Result<String, RuntimeError>, String) {
+        let term = parse(source, "tests").unwrap().expression;
+        let mut io = DummyIO::default();
+        let result = eval_with_options(
+            Box::new(term),
+            options(max_depth),
+            &mut builtins::context(),
+            &mut Cache::new(),
+            &mut io,
+        );
+
+        (result.map(|value| value.to_string()), io.0)
+    }
+
+    fn vm(source: &str, max_depth: usize) -> (Result<String, RuntimeError>, String) {
+        let globals = builtins::context();
+        let program = compile(&parse(source, "tests").unwrap().expression, &globals);
+        let mut io = DummyIO::default();
+        let result = run_with_options(
+            &program,
+            options(max_depth),
+            &globals,
+            &mut Cache::new(),
+            &mut io,
+        );
+
+        (result.map(|value| value.to_string()), io.0)
+    }
+
+    fn assert_same(source: &str) {
+        let (expected_result, expected_output) = tree_walker(source, MAX_DEPTH);
+        let (result, output) = vm(source, MAX_DEPTH);
+
+        assert_eq!(output, expected_output);
+        match (result, expected_result) {
+            (Ok(value), Ok(expected)) => assert_eq!(value, expected),
+            (Err(error), Err(expected)) => {
+                assert_eq!(error.message, expected.message);
+                assert_eq!(error.full_text, expected.full_text);
+                assert_eq!(error.location, expected.location);
+                assert_eq!(error.note, expected.note);
+            }
+            (result, expected) => panic!("expected {expected:?}, found {result:?}"),
+        }
+    }
+
+    #[test]
+    fn examples() {
+        assert_same(include_str!("../examples/fib.rinha"));
+        assert_same(include_str!("../examples/sum.rinha"));
+        assert_same(include_str!("../examples/combination.rinha"));
+        assert_same(include_str!("../examples/hello-world.rinha"));
+    }
+
+    #[test]
+    fn closures_capture_enclosing_scopes() {
+        assert_same(
+            "let add = fn (a) => { fn (b) => { fn (c) => { a + b + c } } }; print(add(1)(2)(3))",
+        );
+        assert_same("let x = 1; let f = fn () => { x }; let x = 2; print((f(), x))");
+    }
+
+    #[test]
+    fn memoization_distinguishes_captured_values() {
+        let source =
+            "let add = fn (a) => { fn (b) => { a + b } }; let _ = print(add(1)(1)); print(add(2)(1))";
+
+        assert_eq!(vm(source, MAX_DEPTH).1, "2\n3\n");
+        assert_same(source);
+    }
+
+    #[test]
+    fn recursion_through_nested_closures() {
+        assert_same(
+            "let count = fn (n) => { let go = fn () => { if (n == 0) { 0 } else { 1 + count(n - 1) } }; go() }; print(count(5))",
+        );
+    }
+
+    #[test]
+    fn tuples_and_print_order() {
+        assert_same("let t = (print(1), print(\"a\")); print((second(t), first(t)))");
+    }
+
+    #[test]
+    fn and_or_short_circuit() {
+        assert_same("let _ = print(false && print(1)); print(true || print(2))");
+        assert_same("print(print(true) && print(false) || print(true))");
+        assert_same("print(true && 1)");
+        assert_same("print(1 || print(2))");
+        assert_same("let f = fn (n) => { n == 0 || f(n - 1) }; print(f(5))");
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let source =
+            "let fact = fn (n) => { if (n == 0) { 1 } else { n * fact(n - 1) } }; print(fact(25))";
+        assert_same(source);
+
+        let globals = builtins::context();
+        let program = compile(&parse(source, "tests").unwrap().expression, &globals);
+        let options = Options {
+            overflow: Overflow::Promote,
+            ..Options::default()
+        };
+        let mut io = DummyIO::default();
+        run_with_options(&program, options, &globals, &mut Cache::new(), &mut io).unwrap();
+
+        assert_eq!(io.0, "15511210043330985984000000\n");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_same("let x = 1; x(print(2))");
+        assert_same("if (print(1)) { 1 } else { 2 }");
+        assert_same("print(1) + true");
+        assert_same("first(1)");
+        assert_same("second(\"a\")");
+        assert_same("let f = fn () => { y }; f()");
+        assert_same("1 / 0");
+        assert_same("let f = fn (a, b) => { a }; f(print(1))");
+        assert_same("let f = fn (a) => { a }; f(1, print(2))");
+    }
+
+    #[test]
+    fn builtins() {
+        assert_same("print(substring(to_string(max(12, 3) * 2), 0, 1))");
+        assert_same("let length = fn (s) => { 0 }; print(length(\"abc\"))");
+        assert_same("let f = length; print(f(\"abc\"))");
+        assert_same("abs(parse_int(\"-\"))");
+        assert_same("length(1, 2)");
+    }
+
+    #[test]
+    fn profiles_match() {
+        let source = "let fib = fn (n) => { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; \
+            let go = fn (n, acc) => { if (n == 0) { acc } else { go(n - 1, acc + fib(n)) } }; \
+            let _ = print(go(10, 0)); (fn (x) => { x })(print(1)) + 1 / 0";
+        let term = parse(source, "tests").unwrap().expression;
+
+        let counts = |profiler: Profiler| {
+            let mut counts: Vec<_> = profiler
+                .functions()
+                .into_iter()
+                .map(|f| (f.name, f.location.start, f.calls, f.cache_hits))
+                .collect();
+            counts.sort();
+            counts
+        };
+
+        let tree = Profiler::new(&term);
+        let result = eval_profiled(
+            Box::new(term.clone()),
+            Options::default(),
+            &tree,
+            &mut builtins::context(),
+            &mut Cache::new(),
+            &mut DummyIO::default(),
+        );
+        assert!(result.is_err());
+
+        let vm = Profiler::new(&term);
+        let globals = builtins::context();
+        let result = run_profiled(
+            &compile(&term, &globals),
+            Options::default(),
+            &vm,
+            &globals,
+            &mut Cache::new(),
+            &mut DummyIO::default(),
+        );
+        assert!(result.is_err());
+
+        let tree = counts(tree);
+        let names: Vec<_> = tree.iter().map(|(name, ..)| name.as_str()).collect();
+        assert_eq!(names, ["<anonymous>", "<main>", "fib", "go"]);
+        assert_eq!(tree, counts(vm));
+    }
+
+    #[test]
+    fn deep_recursion() {
+        let (result, _) = vm(
+            "let f = fn (n) => { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)",
+            1_000_000,
+        );
+
+        assert_eq!(result.unwrap(), "100000");
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let recursion = "let f = fn (n) => { if (n == 0) { 0 } else { 1 + f(n - 1) } }";
+
+        assert_same(&format!("{recursion}; print(f(99))"));
+        assert_same(&format!("{recursion}; print(f(100))"));
+        assert_same(&format!(
+            "{recursion}; let g = fn (n) => {{ f(n) }}; print(g(99))"
+        ));
+        assert_same(&format!("{recursion}; print(1 + f(99))"));
+    }
+
+    #[test]
+    fn tail_calls_keep_their_depth() {
+        assert_same(
+            "let f = fn (n, acc) => { if (n == 0) { acc } else { let m = n - 1; f(m, acc + n) } }; print(f(1000, 0))",
+        );
+        assert_same(
+            "let count = fn (n) => { if (n == 0) { print(n) } else { count(n - 1) } }; count(1000)",
+        );
+    }
+}
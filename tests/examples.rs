@@ -0,0 +1,77 @@
+use std::{path::Path, process::Command};
+
+use lipsum::{
+    ast::Term,
+    bytecode,
+    cache::Cache,
+    interpreter::{eval_with_options, Capture, Options, RuntimeError, Value},
+    testing::{self, Expected},
+    vm,
+};
+
+/// Runs every example with `eval`, panicking with the report of the ones
+/// that don't match their `.expected` file.
+fn assert_examples<E>(eval: E)
+where
+    E: Fn(Term, &mut Capture) -> Result<Value, RuntimeError>,
+{
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let programs = testing::discover(&examples).unwrap();
+    assert!(!programs.is_empty());
+
+    let failures: String = programs
+        .iter()
+        .filter_map(|(path, expected)| {
+            let expected = Expected::parse(&std::fs::read_to_string(expected).unwrap());
+            let outcome = testing::run(path, &eval).unwrap();
+
+            testing::compare(&expected, &outcome)
+                .err()
+                .map(|report| format!("{}\n{report}\n", path.display()))
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "{failures}");
+}
+
+#[test]
+fn tree_walker() {
+    assert_examples(|term, io| {
+        eval_with_options(
+            Box::new(term),
+            Options::default(),
+            &mut testing::context(),
+            &mut Cache::new(),
+            io,
+        )
+    });
+}
+
+#[test]
+fn vm() {
+    assert_examples(|term, io| {
+        let globals = testing::context();
+
+        vm::run_with_options(
+            &bytecode::compile(&term, &globals),
+            Options::default(),
+            &globals,
+            &mut Cache::new(),
+            io,
+        )
+    });
+}
+
+#[test]
+fn test_command() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let output = Command::new(env!("CARGO_BIN_EXE_lipsum"))
+        .arg("test")
+        .arg(&examples)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.ends_with(" passed, 0 failed\n"), "{stdout}");
+}